@@ -1,4 +1,4 @@
 fn main() {
-    // Needed for winapi::GetKeyState
+    // Needed for winapi::GetKeyState and winapi::GetAsyncKeyState
     println!("cargo:rustc-link-lib=dylib=user32");
 }
@@ -1,86 +1,354 @@
+pub mod aimpoint;
+pub mod predict;
+pub mod recoil;
+pub mod smooth;
+pub mod target;
+
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::dashboard::Board;
 use crate::entities::{self, Player};
-use crate::proc::{self, Read, Write};
-use crate::winapi;
+use crate::feature::{Feature, Setting};
+use crate::input::{Activation, InputSource};
+use crate::math::Angles;
+use crate::proc::{self, ProcessMemory, Read, Write};
+use crate::scheduler::WorldSnapshot;
+use crate::weapons::{Recoil, Weapon};
+
+use aimpoint::AimPoint;
+use predict::VelocityTracker;
+use recoil::Compensator;
+use smooth::Smoothing;
+use target::{Lock, TargetSelector};
+
+pub struct Settings {
+    pub enabled: bool,
+    pub activation: Activation,
+    pub input: Box<dyn InputSource>,
+    pub selector: Box<dyn TargetSelector>,
+    pub smoothing: Smoothing,
+    pub aim_point: AimPoint,
+    /// How often the aim is updated while active
+    pub tick: Duration,
+    /// How often the hotkey is checked while inactive
+    pub idle_tick: Duration,
+    /// Only aim at players we have a line of sight to
+    pub visible_only: bool,
+    /// Aim where moving targets will be rather than where they are
+    pub prediction: bool,
+    /// How long it takes for a shot to register with the server
+    pub latency: Duration,
+    /// How long to wait after losing a target before locking onto another
+    pub switch_delay: Duration,
+    /// How much of the current weapon's recoil to counter while firing, from 0 to 1
+    pub recoil_compensation: f32,
+}
 
-struct Angle {
-    yaw: f32,
-    pitch: f32,
+/// What the aimbot keeps track of between ticks
+struct State {
+    velocities: VelocityTracker,
+    lock: Lock,
+    recoil: Compensator,
 }
 
-pub fn spawn_thread(pid: proc::Pid) {
-    std::thread::spawn(move || run(pid));
+impl State {
+    fn new(settings: &Settings) -> State {
+        State {
+            velocities: VelocityTracker::new(),
+            lock: Lock::new(settings.switch_delay),
+            recoil: Compensator::new(settings.recoil_compensation),
+        }
+    }
 }
 
-fn run(pid: proc::Pid) {
-    let handle = proc::open(pid).expect("failed to open process");
+const SCHEMA: &[Setting] = &[
+    Setting {
+        section: "hotkeys",
+        key: "aimbot",
+        description: "Key that switches it on, and whether to hold or toggle it",
+    },
+    Setting {
+        section: "aimbot",
+        key: "tick",
+        description: "Milliseconds between updates while active",
+    },
+    Setting {
+        section: "aimbot",
+        key: "idle_tick",
+        description: "Milliseconds between hotkey checks while inactive",
+    },
+    Setting {
+        section: "aimbot",
+        key: "target",
+        description: "How to pick a target, e.g. closest or weighted,distance=1,angle=4",
+    },
+    Setting {
+        section: "aimbot",
+        key: "aim_point",
+        description: "Where to aim: head, chest, centre, feet or nearest",
+    },
+    Setting {
+        section: "aimbot",
+        key: "max_degrees_per_tick",
+        description: "The furthest the view turns in one update",
+    },
+    Setting {
+        section: "aimbot",
+        key: "easing",
+        description: "How turning slows down near the target: constant, linear or smoothstep",
+    },
+    Setting {
+        section: "aimbot",
+        key: "ramp",
+        description: "Degrees from the target at which easing starts",
+    },
+    Setting {
+        section: "aimbot",
+        key: "jitter",
+        description: "Degrees of random noise added to each turn",
+    },
+    Setting {
+        section: "aimbot",
+        key: "visible_only",
+        description: "Only aim at players in line of sight",
+    },
+    Setting {
+        section: "aimbot",
+        key: "prediction",
+        description: "Lead moving targets",
+    },
+    Setting {
+        section: "aimbot",
+        key: "latency",
+        description: "Milliseconds a shot takes to register, for prediction",
+    },
+    Setting {
+        section: "aimbot",
+        key: "switch_delay",
+        description: "Milliseconds to wait after losing a target before picking another",
+    },
+    Setting {
+        section: "aimbot",
+        key: "recoil_compensation",
+        description: "How much weapon recoil to counter, from 0 to 1",
+    },
+];
+
+impl Settings {
+    pub fn new(config: &Config, input: Box<dyn InputSource>) -> Settings {
+        let aimbot = &config.aimbot;
+        Settings {
+            enabled: config.features.aimbot,
+            activation: Activation::new(aimbot.hotkey.key, aimbot.hotkey.mode),
+            input,
+            selector: selector(&aimbot.target),
+            smoothing: Smoothing::new(
+                aimbot.max_degrees_per_tick,
+                aimbot.easing,
+                aimbot.ramp,
+                aimbot.jitter,
+            ),
+            aim_point: aimbot.aim_point,
+            tick: aimbot.tick,
+            idle_tick: aimbot.idle_tick,
+            visible_only: aimbot.visible_only,
+            prediction: aimbot.prediction,
+            latency: aimbot.latency,
+            switch_delay: aimbot.switch_delay,
+            recoil_compensation: aimbot.recoil_compensation,
+        }
+    }
 
-    while proc::still_active(handle).expect("failed to check process exit code") {
-        run_once(handle);
+    /// Take on a new configuration, keeping whether the aimbot is toggled on
+    pub fn apply(&mut self, config: &Config) {
+        let aimbot = &config.aimbot;
+        self.enabled = config.features.aimbot;
+        self.activation.key = aimbot.hotkey.key;
+        self.activation.mode = aimbot.hotkey.mode;
+        self.selector = selector(&aimbot.target);
+        self.smoothing.max_degrees_per_tick = aimbot.max_degrees_per_tick;
+        self.smoothing.easing = aimbot.easing;
+        self.smoothing.ramp = aimbot.ramp;
+        self.smoothing.jitter = aimbot.jitter;
+        self.aim_point = aimbot.aim_point;
+        self.tick = aimbot.tick;
+        self.idle_tick = aimbot.idle_tick;
+        self.visible_only = aimbot.visible_only;
+        self.prediction = aimbot.prediction;
+        self.latency = aimbot.latency;
+        self.switch_delay = aimbot.switch_delay;
+        self.recoil_compensation = aimbot.recoil_compensation;
     }
+}
+
+fn selector(spec: &str) -> Box<dyn TargetSelector> {
+    // The configuration checks target descriptions as it reads them.
+    target::from_spec(spec).expect("invalid target description")
+}
 
-    proc::close(handle).expect("failed to close process");
+/// Aims at other players while its hotkey says so
+pub struct Aimbot {
+    settings: Settings,
+    state: State,
+    /// Where to report who we're aiming at
+    board: Board,
 }
 
-fn run_once(handle: proc::Handle) {
-    if !capslock_enabled() {
-        std::thread::sleep(std::time::Duration::from_millis(100));
-        return;
+impl Aimbot {
+    pub fn new(settings: Settings, board: Board) -> Aimbot {
+        let state = State::new(&settings);
+        Aimbot {
+            settings,
+            state,
+            board,
+        }
     }
+}
 
-    let my_addr = proc::Address::read(handle, 0x50f4f4).expect("failed to read player pointer");
-    let me = Player::read(handle, my_addr).expect("failed to read player entity");
+impl Feature for Aimbot {
+    fn name(&self) -> &'static str {
+        "aimbot"
+    }
 
-    // Don't aim while dead: it's awkward.
-    if me.health <= 0 {
-        return;
+    fn schema(&self) -> &'static [Setting] {
+        SCHEMA
     }
 
-    let players = entities::player_list(handle).expect("failed to read player list");
-    if let Some(target_player) = closest_living(&me, &players) {
-        let angle = calc_angle(&me, target_player);
-        aim(handle, my_addr, &angle);
+    fn configure(&mut self, config: &Config) {
+        self.settings.apply(config);
+        self.state.lock.switch_delay = self.settings.switch_delay;
+        self.state.recoil.strength = self.settings.recoil_compensation;
     }
-}
 
-pub fn capslock_enabled() -> bool {
-    unsafe { winapi::GetKeyState(winapi::VK_CAPITAL) & 1 == 1 }
-}
+    fn on_attach(&mut self, _memory: &mut dyn ProcessMemory) {
+        // Players, shot counts and targets from a previous game mean nothing in this one.
+        self.state = State::new(&self.settings);
+    }
 
-fn closest_living<'a>(me: &Player, players: &'a Vec<Player>) -> Option<&'a Player> {
-    players
-        .iter()
-        .filter(|p| p.health > 0)
-        .min_by(|x, y| distance(me, x).partial_cmp(&distance(me, y)).unwrap())
-}
+    fn on_tick(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        memory: &mut dyn ProcessMemory,
+    ) -> Result<Duration, String> {
+        let active = run_once(snapshot, memory, &mut self.settings, &mut self.state)?;
+
+        let target = self
+            .state
+            .lock
+            .slot()
+            .and_then(|slot| snapshot.players().find(|p| p.slot == Some(slot)));
+        self.board
+            .set_aimbot(active, target.map(|target| target.name.as_str()));
 
-fn distance(src: &Player, dst: &Player) -> f32 {
-    let (x, y, z) = (dst.x - src.x, dst.y - src.y, dst.z - src.z);
-    let base = (x.powi(2) + y.powi(2)).sqrt();
-    (base.powi(2) + z.powi(2)).sqrt()
+        // Run at a fixed rate while active so that smoothing turns at a predictable speed, and
+        // only check in now and then otherwise.
+        if active {
+            Ok(self.settings.tick)
+        } else {
+            Ok(self.settings.idle_tick)
+        }
+    }
 }
 
-fn calc_angle(src: &Player, dst: &Player) -> Angle {
-    let (x, y, z) = (dst.x - src.x, dst.y - src.y, dst.z - src.z);
-    let base = (x.powi(2) + y.powi(2)).sqrt();
+/// Returns whether the aimbot is active
+fn run_once(
+    snapshot: &WorldSnapshot,
+    memory: &mut dyn ProcessMemory,
+    settings: &mut Settings,
+    state: &mut State,
+) -> Result<bool, String> {
+    // Poll even while disabled, so that key presses from back then aren't seen once re-enabled.
+    let active = settings.activation.poll(settings.input.as_mut());
+    if !settings.enabled || !active {
+        state.lock.release();
+        return Ok(false);
+    }
+
+    let (me, my_addr) = (&snapshot.me, snapshot.my_addr);
 
-    let mut yaw = y.atan2(x).to_degrees() + 90.0;
-    if yaw <= 0.0 {
-        yaw += 360.0;
+    // Don't aim while dead: it's awkward.
+    if me.health <= 0 {
+        state.lock.release();
+        return Ok(true);
     }
 
-    let pitch = (z / base).atan().to_degrees();
+    // Keep track of shots even when there's nobody to aim at, so that they aren't counted as new
+    // once there is.
+    let weapon = Weapon::read_current(&mut *memory, my_addr)
+        .map_err(|err| format!("failed to read weapon: {}", err))?;
+    let recoil = Recoil::read(&mut *memory, my_addr)
+        .map_err(|err| format!("failed to read recoil: {}", err))?;
+    let shots = Weapon::read_shots(&mut *memory, my_addr)
+        .map_err(|err| format!("failed to read shot count: {}", err))?;
+    let attacking = bool::read(
+        &mut *memory,
+        my_addr + entities::PLAYER_ATTACKING_OFFSET as u32,
+    )
+    .map_err(|err| format!("failed to read attack flag: {}", err))?;
+    let kick = state.recoil.update(weapon, recoil, shots, attacking);
+
+    let players: Vec<Player> = snapshot.players().cloned().collect();
+    state.velocities.update(&players, snapshot.taken_at);
+
+    let world = match &snapshot.world {
+        Some(world) if settings.visible_only => Some(world.as_ref()),
+        // Without the map, there's no telling who's in sight.
+        None if settings.visible_only => return Ok(true),
+        _ => None,
+    };
+
+    // In team modes, leave teammates alone, and drop anyone we can't see any part of that we'd
+    // want to aim at.
+    let players: Vec<Player> = players
+        .into_iter()
+        .filter(|p| snapshot.mode.hostile(me, p))
+        .filter(|p| settings.aim_point.locate(me, p, world).is_some())
+        .collect();
+
+    let selected = state
+        .lock
+        .select(settings.selector.as_ref(), me, &players, snapshot.taken_at);
+    let target_player = match selected {
+        Some(target_player) => target_player,
+        None => return Ok(true),
+    };
+
+    let shooter = me.eye();
+    let mut target = match settings.aim_point.locate(me, target_player, world) {
+        Some(target) => target,
+        None => return Ok(true),
+    };
+
+    if settings.prediction && predict::leads(weapon) {
+        let velocity = state.velocities.velocity(target_player);
+        target = predict::lead(target, velocity, settings.latency);
+    }
 
-    Angle { yaw, pitch }
+    // The recoil is cancelled outright; only the turn towards the target is smoothed.
+    let angle = settings.smoothing.step(
+        recoil::steady(me.view, kick),
+        Angles::towards(shooter, target),
+    );
+    aim(memory, my_addr, angle.normalize())?;
+    Ok(true)
 }
 
-fn aim(handle: proc::Handle, player_addr: proc::Address, angle: &Angle) {
+fn aim(
+    memory: &mut dyn ProcessMemory,
+    player_addr: proc::Address,
+    angle: Angles,
+) -> Result<(), String> {
     angle
         .yaw
-        .write(handle, player_addr + entities::PLAYER_YAW_OFFSET as u32)
-        .expect("failed to set player yaw");
+        .write(
+            &mut *memory,
+            player_addr + entities::PLAYER_YAW_OFFSET as u32,
+        )
+        .map_err(|err| format!("failed to set player yaw: {}", err))?;
 
     angle
         .pitch
-        .write(handle, player_addr + entities::PLAYER_PITCH_OFFSET as u32)
-        .expect("failed to set player pitch");
+        .write(memory, player_addr + entities::PLAYER_PITCH_OFFSET as u32)
+        .map_err(|err| format!("failed to set player pitch: {}", err))
 }
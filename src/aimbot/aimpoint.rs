@@ -0,0 +1,199 @@
+//! Which part of the target's body to aim at
+
+use crate::entities::Player;
+use crate::math::{Angles, Vec3};
+use crate::world::World;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AimPoint {
+    Head,
+    Chest,
+    CentreMass,
+    Feet,
+    /// Whichever of the other points is visible and closest to the crosshair
+    NearestVisible,
+}
+
+/// The fixed points, from the top of the body down
+const BODY_POINTS: [AimPoint; 4] = [
+    AimPoint::Head,
+    AimPoint::Chest,
+    AimPoint::CentreMass,
+    AimPoint::Feet,
+];
+
+impl AimPoint {
+    pub fn from_name(name: &str) -> Result<AimPoint, String> {
+        match name {
+            "head" => Ok(AimPoint::Head),
+            "chest" => Ok(AimPoint::Chest),
+            "centre" | "center" => Ok(AimPoint::CentreMass),
+            "feet" => Ok(AimPoint::Feet),
+            "nearest" => Ok(AimPoint::NearestVisible),
+            _ => Err(format!("unknown aim point: {}", name)),
+        }
+    }
+
+    /// Height above the feet as a fraction of eye height; crouching lowers every point along
+    /// with the eyes.
+    fn height_fraction(self) -> f32 {
+        match self {
+            AimPoint::Head | AimPoint::NearestVisible => 1.0,
+            AimPoint::Chest => 0.75,
+            AimPoint::CentreMass => 0.5,
+            AimPoint::Feet => 0.1,
+        }
+    }
+
+    fn position(self, target: &Player) -> Vec3 {
+        let height = target.eye_height * self.height_fraction();
+        target.feet + Vec3::new(0.0, 0.0, height)
+    }
+
+    /// Find where to aim on `target`, as seen by `me`
+    ///
+    /// Given a world, points that can't be seen are skipped, and `None` is returned if no part of
+    /// the target is visible.
+    pub fn locate(self, me: &Player, target: &Player, world: Option<&World>) -> Option<Vec3> {
+        let eye = me.eye();
+        let visible = |point: &Vec3| match world {
+            Some(world) => world.visible(eye, *point),
+            None => true,
+        };
+
+        if self != AimPoint::NearestVisible {
+            let point = self.position(target);
+            return if visible(&point) { Some(point) } else { None };
+        }
+
+        BODY_POINTS
+            .iter()
+            .map(|p| p.position(target))
+            .filter(visible)
+            .min_by(|a, b| {
+                let a = me.view.distance(Angles::towards(eye, *a));
+                let b = me.view.distance(Angles::towards(eye, *b));
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Cell, CellKind};
+
+    const OPEN: Cell = Cell {
+        kind: CellKind::Space,
+        floor: 0,
+        ceil: 16,
+        vdelta: 0,
+    };
+
+    /// A 4x4 map, open from height 0 to 16 except for a ledge hanging down to a height of 4 in
+    /// the given cells
+    fn world(ledges: &[(usize, usize)]) -> World {
+        let mut cells = vec![OPEN; 16];
+        for &(x, y) in ledges {
+            cells[y * 4 + x] = Cell { ceil: 4, ..OPEN };
+        }
+        World::new(2, cells).unwrap()
+    }
+
+    fn player(x: f32, y: f32, eye_height: f32) -> Player {
+        Player {
+            position: Vec3::new(x, y, eye_height),
+            feet: Vec3::new(x, y, 0.0),
+            eye_height,
+            ..Player::default()
+        }
+    }
+
+    /// Us at the far end of the map, looking along -y (a yaw of 0) at a target's head
+    fn me() -> Player {
+        Player {
+            view: Angles::new(0.0, 0.0),
+            ..player(2.5, 3.5, 4.5)
+        }
+    }
+
+    fn height(point: AimPoint, target: &Player) -> f32 {
+        point.locate(&me(), target, None).unwrap().z
+    }
+
+    #[test]
+    fn points_scale_with_eye_height() {
+        let standing = player(2.5, 0.5, 4.5);
+        let crouching = Player {
+            crouching: true,
+            ..player(2.5, 0.5, 3.15)
+        };
+        // (point, standing height, crouching height)
+        let cases = [
+            (AimPoint::Head, 4.5, 3.15),
+            (AimPoint::Chest, 3.375, 2.3625),
+            (AimPoint::CentreMass, 2.25, 1.575),
+            (AimPoint::Feet, 0.45, 0.315),
+        ];
+        for &(point, standing_height, crouching_height) in &cases {
+            assert!(
+                (height(point, &standing) - standing_height).abs() < 1e-5,
+                "{:?}",
+                point
+            );
+            assert!(
+                (height(point, &crouching) - crouching_height).abs() < 1e-5,
+                "{:?}",
+                point
+            );
+        }
+
+        // Always straight above the feet
+        let point = AimPoint::Chest.locate(&me(), &standing, None).unwrap();
+        assert_eq!((point.x, point.y), (2.5, 0.5));
+    }
+
+    #[test]
+    fn fixed_points_that_cant_be_seen_are_skipped() {
+        let target = player(2.5, 0.5, 4.5);
+        // The ledge hides the head, but we can see under it.
+        let world = world(&[(2, 1)]);
+        assert_eq!(AimPoint::Head.locate(&me(), &target, Some(&world)), None);
+        assert!(AimPoint::Chest
+            .locate(&me(), &target, Some(&world))
+            .is_some());
+    }
+
+    #[test]
+    fn nearest_visible_falls_back_from_a_hidden_head() {
+        let target = player(2.5, 0.5, 4.5);
+        let nearest = |me: &Player, world: Option<&World>| {
+            AimPoint::NearestVisible.locate(me, &target, world)
+        };
+        let [head, chest, _, feet] = BODY_POINTS;
+
+        // Looking at the head picks it, unless it's hidden; then the chest is the closest.
+        assert_eq!(nearest(&me(), None), Some(head.position(&target)));
+        assert_eq!(
+            nearest(&me(), Some(&world(&[]))),
+            Some(head.position(&target))
+        );
+        assert_eq!(
+            nearest(&me(), Some(&world(&[(2, 1)]))),
+            Some(chest.position(&target))
+        );
+
+        // Looking down at the feet picks those instead.
+        let looking_down = Player {
+            view: Angles::new(0.0, -60.0),
+            ..me()
+        };
+        assert_eq!(nearest(&looking_down, None), Some(feet.position(&target)));
+
+        // Nothing to aim at behind a low ceiling all the way across
+        let mut cells = vec![OPEN; 16];
+        cells[4..8].copy_from_slice(&[Cell { ceil: 0, ..OPEN }; 4]);
+        let covered = World::new(2, cells).unwrap();
+        assert_eq!(nearest(&me(), Some(&covered)), None);
+    }
+}
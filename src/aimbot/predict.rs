@@ -0,0 +1,201 @@
+//! Leading moving targets
+//!
+//! Each player's velocity is estimated from how far they moved between successive reads of the
+//! player list. The aim point is then pushed along that velocity by however long the shot takes
+//! to register, which for the game's hitscan guns is the network latency.
+//!
+//! Grenades aren't led: they fly in an arc under gravity and bounce, which a straight-line lead
+//! would only make worse.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::entities::Player;
+use crate::math::Vec3;
+use crate::weapons::Weapon;
+
+/// Samples further apart than this are too stale to derive a velocity from
+const MAX_SAMPLE_GAP: Duration = Duration::from_millis(500);
+
+/// Samples closer together than this are too noisy to derive a velocity from
+const MIN_SAMPLE_GAP: Duration = Duration::from_millis(5);
+
+/// How fast players run, in units per second (`maxspeed` in the game's physics)
+const RUN_SPEED: f32 = 16.0;
+
+/// Jumping, falling and explosions can add a good deal to running, but a jump of more than this
+/// is a respawn or teleport and says nothing about velocity.
+const MAX_SPEED: f32 = RUN_SPEED * 4.0;
+
+/// How much a new velocity measurement counts against the running estimate
+const SMOOTHING: f32 = 0.5;
+
+struct Sample {
+    position: Vec3,
+    at: Instant,
+    velocity: Vec3,
+}
+
+/// Tracks each player's velocity, in units per second, by player list slot
+pub struct VelocityTracker {
+    samples: HashMap<usize, Sample>,
+}
+
+impl VelocityTracker {
+    pub fn new() -> VelocityTracker {
+        VelocityTracker {
+            samples: HashMap::new(),
+        }
+    }
+
+    pub fn update(&mut self, players: &[Player], now: Instant) {
+        // Forget players that left; a new player in their slot shouldn't inherit their velocity.
+        self.samples
+            .retain(|slot, _| players.iter().any(|p| p.slot == Some(*slot)));
+
+        for player in players {
+            let slot = match player.slot {
+                Some(slot) => slot,
+                None => continue,
+            };
+            let position = player.position;
+
+            let velocity = match self.samples.get(&slot) {
+                Some(previous) => {
+                    let elapsed = now.duration_since(previous.at);
+                    if elapsed < MIN_SAMPLE_GAP {
+                        // Keep the older sample so the next measurement spans a useful interval.
+                        continue;
+                    }
+                    estimate(previous, position, elapsed)
+                }
+                None => Vec3::ZERO,
+            };
+
+            self.samples.insert(
+                slot,
+                Sample {
+                    position,
+                    at: now,
+                    velocity,
+                },
+            );
+        }
+    }
+
+    pub fn velocity(&self, player: &Player) -> Vec3 {
+        player
+            .slot
+            .and_then(|slot| self.samples.get(&slot))
+            .map(|sample| sample.velocity)
+            .unwrap_or(Vec3::ZERO)
+    }
+}
+
+fn estimate(previous: &Sample, position: Vec3, elapsed: Duration) -> Vec3 {
+    if elapsed > MAX_SAMPLE_GAP {
+        return Vec3::ZERO;
+    }
+
+    let measured = (position - previous.position) * (1.0 / elapsed.as_secs_f32());
+    if measured.length_squared() > MAX_SPEED * MAX_SPEED {
+        return Vec3::ZERO;
+    }
+
+    previous.velocity + (measured - previous.velocity) * SMOOTHING
+}
+
+/// Whether shots from a weapon can be led, i.e. whether it's hitscan
+pub fn leads(weapon: Weapon) -> bool {
+    weapon != Weapon::Grenade
+}
+
+/// Where a target at `target` moving at `velocity` will be by the time a shot fired now registers
+pub fn lead(target: Vec3, velocity: Vec3, latency: Duration) -> Vec3 {
+    target + velocity * latency.as_secs_f32()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(slot: usize, x: f32) -> Player {
+        Player {
+            slot: Some(slot),
+            position: Vec3::new(x, 0.0, 0.0),
+            ..Player::default()
+        }
+    }
+
+    /// Feed the tracker one player's x positions, 100ms apart, and return the velocity it ends up
+    /// with
+    fn track(positions: &[f32]) -> Vec3 {
+        let mut tracker = VelocityTracker::new();
+        let start = Instant::now();
+        for (index, &x) in positions.iter().enumerate() {
+            let at = start + Duration::from_millis(100 * index as u64);
+            tracker.update(&[player(3, x)], at);
+        }
+        tracker.velocity(&player(3, 0.0))
+    }
+
+    #[test]
+    fn stationary_players_have_no_velocity() {
+        assert_eq!(track(&[5.0, 5.0, 5.0, 5.0]), Vec3::ZERO);
+    }
+
+    #[test]
+    fn constant_velocity_is_converged_on() {
+        let positions: Vec<f32> = (0..20).map(|i| i as f32).collect();
+        let velocity = track(&positions);
+        assert!((velocity.x - 10.0).abs() < 0.01, "{:?}", velocity);
+        assert_eq!((velocity.y, velocity.z), (0.0, 0.0));
+    }
+
+    #[test]
+    fn teleports_reset_velocity() {
+        let velocity = track(&[0.0, 1.0, 2.0, 3.0, 500.0]);
+        assert_eq!(velocity, Vec3::ZERO);
+        // Moving on normally from there starts over.
+        let velocity = track(&[0.0, 1.0, 2.0, 3.0, 500.0, 501.0]);
+        assert!((velocity.x - 5.0).abs() < 0.01, "{:?}", velocity);
+    }
+
+    #[test]
+    fn stale_samples_are_ignored() {
+        let mut tracker = VelocityTracker::new();
+        let start = Instant::now();
+        tracker.update(&[player(0, 0.0)], start);
+        tracker.update(&[player(0, 1.0)], start + MAX_SAMPLE_GAP * 2);
+        assert_eq!(tracker.velocity(&player(0, 0.0)), Vec3::ZERO);
+    }
+
+    #[test]
+    fn players_that_leave_are_forgotten() {
+        let mut tracker = VelocityTracker::new();
+        let start = Instant::now();
+        tracker.update(&[player(0, 0.0)], start);
+        tracker.update(&[player(0, 1.0)], start + Duration::from_millis(100));
+        assert_ne!(tracker.velocity(&player(0, 0.0)), Vec3::ZERO);
+
+        tracker.update(&[], start + Duration::from_millis(200));
+        assert_eq!(tracker.velocity(&player(0, 0.0)), Vec3::ZERO);
+    }
+
+    #[test]
+    fn lead_moves_along_the_velocity_for_the_latency() {
+        let target = Vec3::new(10.0, 20.0, 4.0);
+        let velocity = Vec3::new(8.0, -4.0, 0.0);
+        assert_eq!(
+            lead(target, velocity, Duration::from_millis(500)),
+            Vec3::new(14.0, 18.0, 4.0)
+        );
+        assert_eq!(lead(target, velocity, Duration::ZERO), target);
+    }
+
+    #[test]
+    fn grenades_are_not_led() {
+        assert!(!leads(Weapon::Grenade));
+        assert!(leads(Weapon::Assault));
+    }
+}
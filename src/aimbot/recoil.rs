@@ -0,0 +1,108 @@
+//! Countering weapon recoil while firing
+//!
+//! Every shot kicks the view upwards by an amount the game works out from the gun's `guninfo`
+//! and how far into the burst it is. Watching the weapon's shot counter tells us when shots went
+//! off, so the pitch we write can be lowered by the kick they're about to add. Spread isn't
+//! countered; see [`Recoil`].
+
+use crate::math::Angles;
+use crate::weapons::{Recoil, Weapon};
+
+pub struct Compensator {
+    /// How much of each weapon's recoil to counter, from 0 (none) to 1 (all of it)
+    pub strength: f32,
+    last: Option<(Weapon, i32)>,
+}
+
+impl Compensator {
+    pub fn new(strength: f32) -> Compensator {
+        Compensator {
+            strength,
+            last: None,
+        }
+    }
+
+    /// How many degrees to lower the pitch by to cancel the shots fired since the last update
+    pub fn update(&mut self, weapon: Weapon, recoil: Recoil, shots: i32, attacking: bool) -> f32 {
+        let previous = self.last.replace((weapon, shots));
+        if !attacking {
+            return 0.0;
+        }
+
+        // Switching weapons or reloading resets the counter, which says nothing about recoil.
+        match previous {
+            Some((previous_weapon, previous_shots))
+                if previous_weapon == weapon && shots > previous_shots =>
+            {
+                let kicks: f32 = (previous_shots..shots).map(|shot| recoil.kick(shot)).sum();
+                kicks * self.strength
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// The view with `kick` degrees of recoil already taken out
+///
+/// Smoothing should start from here rather than from the view itself: it would otherwise see the
+/// kick as part of the distance to the target and turn against it a second time, and slowly.
+pub fn steady(view: Angles, kick: f32) -> Angles {
+    Angles::new(view.yaw, view.pitch - kick)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aimbot::smooth::{Easing, Smoothing};
+
+    #[test]
+    fn assault_rifle_burst() {
+        // Kicks of 1 degree, then 1.25, 2, 3.25 and then 3.5 for good
+        let recoil = Recoil {
+            base: 10,
+            increase: 2,
+            max: 35,
+        };
+        let mut compensator = Compensator::new(1.0);
+
+        // (shots, attacking, expected degrees)
+        let sequence = [
+            (0, false, 0.0),
+            // The first shot, and then two at once between updates
+            (1, true, 1.0),
+            (3, true, 1.25 + 2.0),
+            (3, true, 0.0),
+            // Letting go of the trigger
+            (4, false, 0.0),
+            // Reloading resets the counter.
+            (0, true, 0.0),
+            (1, true, 1.0),
+        ];
+        for (step, &(shots, attacking, degrees)) in sequence.iter().enumerate() {
+            let lowered = compensator.update(Weapon::Assault, recoil, shots, attacking);
+            assert!((lowered - degrees).abs() < 1e-6, "step {}", step);
+        }
+
+        // A weapon switch isn't a shot.
+        assert_eq!(compensator.update(Weapon::Sniper, recoil, 5, true), 0.0);
+
+        // Far into a burst every shot kicks the most.
+        compensator.strength = 0.5;
+        assert_eq!(compensator.update(Weapon::Sniper, recoil, 7, true), 3.5);
+    }
+
+    #[test]
+    fn kick_is_countered_once() {
+        let mut smoothing = Smoothing::new(1.0, Easing::Constant, 0.0, 0.0);
+        let view = Angles::new(90.0, 10.0);
+
+        // Already on target, so only the kick is taken out, and all of it at once despite the
+        // turn limit.
+        let on_target = smoothing.step(steady(view, 3.0), Angles::new(90.0, 7.0));
+        assert_eq!(on_target, Angles::new(90.0, 7.0));
+
+        // Otherwise smoothing turns its usual step on top.
+        let below = smoothing.step(steady(view, 3.0), Angles::new(90.0, 0.0));
+        assert!((below.pitch - 6.0).abs() < 1e-4, "{:?}", below);
+    }
+}
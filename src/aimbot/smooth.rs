@@ -0,0 +1,226 @@
+//! Gradual, human-looking movement from the current view angles towards the target's
+//!
+//! Rather than snapping straight onto the target, each tick turns by at most a configurable
+//! number of degrees. An easing curve slows the turn down as the crosshair closes in, and optional
+//! jitter keeps the motion from being perfectly straight.
+
+use crate::math::{self, Angles};
+
+/// Below this many degrees away, the target is considered reached
+const ARRIVED: f32 = 0.01;
+
+/// The slowest an easing curve is allowed to make us turn, so that we always converge
+const MIN_STEP: f32 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    /// Turn at full speed until the target is reached
+    Constant,
+    /// Slow down linearly over the last `ramp` degrees
+    Linear,
+    /// Slow down along a smoothstep curve over the last `ramp` degrees
+    Smoothstep,
+}
+
+impl Easing {
+    pub fn from_name(name: &str) -> Result<Easing, String> {
+        match name {
+            "constant" => Ok(Easing::Constant),
+            "linear" => Ok(Easing::Linear),
+            "smoothstep" => Ok(Easing::Smoothstep),
+            _ => Err(format!("unknown easing: {}", name)),
+        }
+    }
+
+    /// Map how far along the ramp we still are (1 at its start, 0 at the target) to the fraction
+    /// of full speed to turn at
+    fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Constant => 1.0,
+            Easing::Linear => t,
+            Easing::Smoothstep => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+pub struct Smoothing {
+    /// The most we turn in a single tick; zero or less snaps straight onto the target
+    pub max_degrees_per_tick: f32,
+    pub easing: Easing,
+    /// How many degrees from the target the easing curve starts slowing us down
+    pub ramp: f32,
+    /// Maximum random offset, in degrees, added to each step
+    pub jitter: f32,
+    rng: XorShift,
+}
+
+impl Smoothing {
+    pub fn new(max_degrees_per_tick: f32, easing: Easing, ramp: f32, jitter: f32) -> Smoothing {
+        Smoothing {
+            max_degrees_per_tick,
+            easing,
+            ramp,
+            jitter,
+            rng: XorShift::from_time(),
+        }
+    }
+
+    /// Compute the angles to write this tick to move from `current` towards `target`
+    pub fn step(&mut self, current: Angles, target: Angles) -> Angles {
+        let delta_yaw = math::yaw_delta(current.yaw, target.yaw);
+        let delta_pitch = target.pitch - current.pitch;
+        let remaining = current.distance(target);
+
+        if remaining < ARRIVED || self.max_degrees_per_tick <= 0.0 {
+            return target.normalize();
+        }
+
+        let speed = if self.ramp > 0.0 {
+            self.easing.apply(remaining / self.ramp)
+        } else {
+            1.0
+        };
+        let step = (self.max_degrees_per_tick * speed)
+            .max(MIN_STEP)
+            .min(remaining);
+
+        // Move along the straight line towards the target, so yaw and pitch arrive together.
+        let scale = step / remaining;
+        let mut yaw = current.yaw + delta_yaw * scale;
+        let mut pitch = current.pitch + delta_pitch * scale;
+
+        if self.jitter > 0.0 {
+            yaw += self.rng.next_signed() * self.jitter;
+            pitch += self.rng.next_signed() * self.jitter;
+        }
+
+        Angles::new(yaw, pitch).normalize()
+    }
+}
+
+/// A tiny xorshift generator; jitter doesn't need anything better, and this avoids a dependency
+struct XorShift(u32);
+
+impl XorShift {
+    fn from_time() -> XorShift {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        // The state must never be zero, or the generator gets stuck there.
+        XorShift(nanos | 1)
+    }
+
+    fn next(&mut self) -> u32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        x
+    }
+
+    /// A uniformly distributed float in [-1, 1]
+    fn next_signed(&mut self) -> f32 {
+        (self.next() as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn smoothing(max_degrees_per_tick: f32, easing: Easing, jitter: f32) -> Smoothing {
+        Smoothing {
+            max_degrees_per_tick,
+            easing,
+            ramp: 20.0,
+            jitter,
+            rng: XorShift(0x2545_f491),
+        }
+    }
+
+    #[test]
+    fn turns_through_zero_rather_than_the_long_way_round() {
+        let mut smoothing = smoothing(5.0, Easing::Constant, 0.0);
+        let target = Angles::new(10.0, 0.0);
+        let mut view = Angles::new(350.0, 0.0);
+        let mut yaws = Vec::new();
+        for _ in 0..4 {
+            view = smoothing.step(view, target);
+            yaws.push(view.yaw);
+        }
+        assert_eq!(yaws, [355.0, 0.0, 5.0, 10.0]);
+
+        // And back again the other way
+        let view = smoothing.step(Angles::new(10.0, 0.0), Angles::new(350.0, 0.0));
+        assert_eq!(view.yaw, 5.0);
+    }
+
+    #[test]
+    fn turns_at_most_the_limit_per_tick() {
+        let mut smoothing = smoothing(4.0, Easing::Constant, 0.0);
+        let view = Angles::new(0.0, 0.0);
+        let target = Angles::new(90.0, 30.0);
+
+        let next = smoothing.step(view, target);
+        assert!((view.distance(next) - 4.0).abs() < 1e-4, "{:?}", next);
+        // Yaw and pitch move in proportion, so they arrive together.
+        assert!(
+            (next.pitch / next.yaw - 30.0 / 90.0).abs() < 1e-4,
+            "{:?}",
+            next
+        );
+
+        // The last step stops on the target instead of overshooting it.
+        let close = Angles::new(88.0, 30.0);
+        assert_eq!(smoothing.step(close, target), target);
+
+        // No limit snaps straight on.
+        smoothing.max_degrees_per_tick = 0.0;
+        assert_eq!(smoothing.step(view, target), target);
+    }
+
+    #[test]
+    fn every_easing_reaches_the_target() {
+        let target = Angles::new(300.0, -20.0);
+        for &easing in &[Easing::Constant, Easing::Linear, Easing::Smoothstep] {
+            let mut smoothing = smoothing(6.0, easing, 0.0);
+            let mut view = Angles::new(20.0, 40.0);
+            let mut ticks = 0;
+            while view != target {
+                let next = smoothing.step(view, target);
+                assert!(
+                    next.distance(target) < view.distance(target),
+                    "{:?}",
+                    easing
+                );
+                view = next;
+                ticks += 1;
+                assert!(ticks < 1000, "{:?} never got there", easing);
+            }
+        }
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let target = Angles::new(180.0, 0.0);
+        let mut steady = smoothing(2.0, Easing::Constant, 0.0);
+        let mut jittery = smoothing(2.0, Easing::Constant, 0.5);
+        let mut seen = 0.0f32;
+        for i in 0..1000 {
+            let view = Angles::new(i as f32 * 0.1, 0.0);
+            let expected = steady.step(view, target);
+            let actual = jittery.step(view, target);
+            let (yaw, pitch) = (
+                math::yaw_delta(expected.yaw, actual.yaw).abs(),
+                (actual.pitch - expected.pitch).abs(),
+            );
+            assert!(yaw <= 0.5 + 1e-4 && pitch <= 0.5 + 1e-4, "{:?}", actual);
+            seen = seen.max(yaw).max(pitch);
+        }
+        // It's noise, not nothing.
+        assert!(seen > 0.4, "{}", seen);
+    }
+}
@@ -0,0 +1,436 @@
+//! Strategies for picking which player the aimbot should aim at
+
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::entities::Player;
+
+/// The strategy used when none is given
+pub const DEFAULT_SPEC: &str = "closest";
+
+pub trait TargetSelector: Send {
+    /// Score a living candidate, lower being better, or return `None` to rule it out entirely
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32>;
+
+    /// Pick the living player with the best score
+    fn select<'a>(&self, me: &Player, players: &'a [Player]) -> Option<&'a Player> {
+        players
+            .iter()
+            .filter(|p| p.health > 0)
+            .filter_map(|p| self.score(me, p).map(|score| (p, score)))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(p, _)| p)
+    }
+}
+
+/// Sticks with one target, tracked by player list slot, instead of re-selecting every tick
+///
+/// The target is kept until it dies, leaves, or drops out of the candidates (because it's out of
+/// sight) or gets ruled out by the selector (because it left the FOV or range). Only then is a new
+/// one picked, after waiting out the switch delay, so the aim doesn't flick between targets as
+/// distances and angles change.
+///
+/// A slot can be handed to someone else between two ticks, so the target's raw name is kept along
+/// with its slot, and a different name in the slot counts as the target having left.
+pub struct Lock {
+    /// How long to wait after losing a target before picking the next one
+    pub switch_delay: Duration,
+    target: Option<(usize, Vec<u8>)>,
+    lost_at: Option<Instant>,
+}
+
+impl Lock {
+    pub fn new(switch_delay: Duration) -> Lock {
+        Lock {
+            switch_delay,
+            target: None,
+            lost_at: None,
+        }
+    }
+
+    pub fn select<'a>(
+        &mut self,
+        selector: &dyn TargetSelector,
+        me: &Player,
+        players: &'a [Player],
+        now: Instant,
+    ) -> Option<&'a Player> {
+        if let Some((slot, name)) = &self.target {
+            let current = players
+                .iter()
+                .find(|p| p.slot == Some(*slot) && p.name_raw == *name)
+                .filter(|p| p.health > 0 && selector.score(me, p).is_some());
+            if current.is_some() {
+                return current;
+            }
+
+            self.target = None;
+            self.lost_at = Some(now);
+        }
+
+        if let Some(lost_at) = self.lost_at {
+            if now.duration_since(lost_at) < self.switch_delay {
+                return None;
+            }
+            self.lost_at = None;
+        }
+
+        let target = selector.select(me, players)?;
+        self.target = target.slot.map(|slot| (slot, target.name_raw.clone()));
+        Some(target)
+    }
+
+    /// The slot of the player currently locked onto
+    pub fn slot(&self) -> Option<usize> {
+        self.target.as_ref().map(|&(slot, _)| slot)
+    }
+
+    /// Forget the current target, so the next selection starts afresh with no delay
+    pub fn release(&mut self) {
+        self.target = None;
+        self.lost_at = None;
+    }
+}
+
+/// Prefer the player closest to us
+pub struct Closest;
+
+impl TargetSelector for Closest {
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32> {
+        // Squared distances rank the same, without the square root.
+        Some(me.position.distance_squared(candidate.position))
+    }
+}
+
+/// Prefer the player closest to our crosshair, i.e. the one needing the smallest turn to aim at
+pub struct SmallestAngle;
+
+impl TargetSelector for SmallestAngle {
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32> {
+        Some(angle_to(me, candidate))
+    }
+}
+
+/// Prefer the player who is easiest to finish off
+pub struct LowestHealth;
+
+impl TargetSelector for LowestHealth {
+    fn score(&self, _me: &Player, candidate: &Player) -> Option<f32> {
+        Some(candidate.health as f32)
+    }
+}
+
+/// Only consider players within a cone around our crosshair, ranking them with another strategy
+pub struct WithinFov {
+    /// Angle between the crosshair and the edge of the cone
+    pub max_degrees: f32,
+    pub inner: Box<dyn TargetSelector>,
+}
+
+impl TargetSelector for WithinFov {
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32> {
+        if angle_to(me, candidate) > self.max_degrees {
+            return None;
+        }
+        self.inner.score(me, candidate)
+    }
+}
+
+/// Only consider players within a given distance, ranking them with another strategy
+pub struct WithinRange {
+    pub max_distance: f32,
+    pub inner: Box<dyn TargetSelector>,
+}
+
+impl TargetSelector for WithinRange {
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32> {
+        let distance_squared = me.position.distance_squared(candidate.position);
+        if distance_squared > self.max_distance * self.max_distance {
+            return None;
+        }
+        self.inner.score(me, candidate)
+    }
+}
+
+/// Rank players by a weighted sum of their distance (in world units), angle from the crosshair
+/// (in degrees) and health
+pub struct Weighted {
+    pub distance: f32,
+    pub angle: f32,
+    pub health: f32,
+}
+
+impl TargetSelector for Weighted {
+    fn score(&self, me: &Player, candidate: &Player) -> Option<f32> {
+        Some(
+            self.distance * distance(me, candidate)
+                + self.angle * angle_to(me, candidate)
+                + self.health * candidate.health as f32,
+        )
+    }
+}
+
+/// Build a selector from a textual description, so the strategy can be chosen at runtime
+///
+/// The description is a comma-separated list. The first item names the strategy: `closest`,
+/// `angle`, `health` or `weighted`. The remaining items are `key=value` options: `fov` and
+/// `range` restrict the candidates of any strategy, while `distance`, `angle` and `health` set
+/// the weights of the `weighted` strategy. For example: `weighted,distance=1,angle=4,fov=45`.
+pub fn from_spec(spec: &str) -> Result<Box<dyn TargetSelector>, String> {
+    let mut parts = spec.split(',').map(str::trim);
+    let name = parts.next().unwrap_or_default();
+
+    let mut fov = None;
+    let mut range = None;
+    let mut weights = Weighted {
+        distance: 1.0,
+        angle: 0.0,
+        health: 0.0,
+    };
+
+    for option in parts {
+        let (key, value) = split_option(option)?;
+        match key {
+            "fov" => fov = Some(value),
+            "range" => range = Some(value),
+            "distance" if name == "weighted" => weights.distance = value,
+            "angle" if name == "weighted" => weights.angle = value,
+            "health" if name == "weighted" => weights.health = value,
+            _ => return Err(format!("unknown target option for {}: {}", name, key)),
+        }
+    }
+
+    let mut selector: Box<dyn TargetSelector> = match name {
+        "closest" => Box::new(Closest),
+        "angle" => Box::new(SmallestAngle),
+        "health" => Box::new(LowestHealth),
+        "weighted" => Box::new(weights),
+        _ => return Err(format!("unknown target strategy: {}", name)),
+    };
+
+    if let Some(max_degrees) = fov {
+        selector = Box::new(WithinFov {
+            max_degrees,
+            inner: selector,
+        });
+    }
+
+    if let Some(max_distance) = range {
+        selector = Box::new(WithinRange {
+            max_distance,
+            inner: selector,
+        });
+    }
+
+    Ok(selector)
+}
+
+fn split_option(option: &str) -> Result<(&str, f32), String> {
+    let mut kv = option.splitn(2, '=');
+    let key = kv.next().unwrap_or_default().trim();
+    let value = kv
+        .next()
+        .ok_or_else(|| format!("target option {} has no value", key))?
+        .trim();
+    let value = value
+        .parse()
+        .map_err(|_| format!("invalid value for target option {}: {}", key, value))?;
+    Ok((key, value))
+}
+
+pub fn distance(src: &Player, dst: &Player) -> f32 {
+    src.position.distance(dst.position)
+}
+
+/// The angle, in degrees, between where `src` is looking and the direction of `dst`
+pub fn angle_to(src: &Player, dst: &Player) -> f32 {
+    src.view
+        .direction()
+        .angle_between(dst.position - src.position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{Angles, Vec3};
+
+    fn player(slot: usize, x: f32, y: f32, health: i32) -> Player {
+        Player {
+            slot: Some(slot),
+            position: Vec3::new(x, y, 0.0),
+            health,
+            ..Player::default()
+        }
+    }
+
+    /// Us at the origin, looking along -y (a yaw of 0)
+    fn me() -> Player {
+        Player {
+            view: Angles::new(0.0, 0.0),
+            health: 100,
+            ..Player::default()
+        }
+    }
+
+    /// Straight ahead at 10; off to the side at 5; nearly ahead at 30 and weak; dead right here
+    fn players() -> Vec<Player> {
+        vec![
+            player(0, 0.0, -10.0, 100),
+            player(1, 5.0, 0.0, 50),
+            player(2, 3.0, -30.0, 20),
+            player(3, 1.0, 0.0, 0),
+        ]
+    }
+
+    #[test]
+    fn strategies_pick_the_expected_player() {
+        let cases = [
+            ("closest", Some(1)),
+            ("angle", Some(0)),
+            ("health", Some(2)),
+            ("closest,fov=45", Some(0)),
+            ("closest,range=8", Some(1)),
+            ("health,range=20", Some(1)),
+            ("angle,fov=1,range=5", None),
+            ("weighted", Some(1)),
+            ("weighted,distance=0,health=1", Some(2)),
+            ("weighted,distance=1,angle=1", Some(0)),
+            (" weighted , distance = 1 , angle = 1 , fov = 3 ", Some(0)),
+        ];
+        let (me, players) = (me(), players());
+        for &(spec, expected) in &cases {
+            let selector = from_spec(spec).unwrap();
+            let picked = selector.select(&me, &players).and_then(|p| p.slot);
+            assert_eq!(picked, expected, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn bad_specs_are_rejected() {
+        let cases = [
+            ("", "unknown target strategy: "),
+            ("nearest", "unknown target strategy: nearest"),
+            ("closest,fov", "target option fov has no value"),
+            (
+                "closest,fov=wide",
+                "invalid value for target option fov: wide",
+            ),
+            (
+                "closest,health=1",
+                "unknown target option for closest: health",
+            ),
+            (
+                "weighted,speed=2",
+                "unknown target option for weighted: speed",
+            ),
+        ];
+        for &(spec, expected) in &cases {
+            assert_eq!(from_spec(spec).err().as_deref(), Some(expected), "{}", spec);
+        }
+        assert!(from_spec(DEFAULT_SPEC).is_ok());
+    }
+
+    #[test]
+    fn angle_to_measures_from_the_crosshair() {
+        let me = me();
+        assert!(angle_to(&me, &player(0, 0.0, -10.0, 100)) < 1e-3);
+        assert!((angle_to(&me, &player(0, 5.0, 0.0, 100)) - 90.0).abs() < 1e-3);
+        assert!((angle_to(&me, &player(0, 0.0, 10.0, 100)) - 180.0).abs() < 1e-3);
+    }
+
+    const DELAY: Duration = Duration::from_millis(250);
+
+    fn named(slot: usize, name: &str, x: f32, y: f32) -> Player {
+        Player {
+            name_raw: name.as_bytes().to_vec(),
+            name: String::from(name),
+            ..player(slot, x, y, 100)
+        }
+    }
+
+    /// Run one selection per list of players, 100ms apart, returning the slots picked
+    fn lock_on(selector: &dyn TargetSelector, ticks: &[Vec<Player>]) -> Vec<Option<usize>> {
+        let (me, start) = (me(), Instant::now());
+        let mut lock = Lock::new(DELAY);
+        ticks
+            .iter()
+            .enumerate()
+            .map(|(tick, players)| {
+                let now = start + Duration::from_millis(100 * tick as u64);
+                let picked = lock
+                    .select(selector, &me, players, now)
+                    .and_then(|p| p.slot);
+                assert_eq!(picked, lock.slot());
+                picked
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lock_sticks_until_the_target_is_lost() {
+        let a = named(0, "a", 0.0, -10.0);
+        let b = named(1, "b", 0.0, -20.0);
+        let closer = named(1, "b", 0.0, -5.0);
+        let dead = Player {
+            health: 0,
+            ..a.clone()
+        };
+        let ticks = [
+            vec![a.clone(), b.clone()],
+            // Someone else being closer isn't enough to switch.
+            vec![a.clone(), closer.clone()],
+            vec![dead, closer.clone()],
+            // The switch delay runs from when the target was lost.
+            vec![closer.clone()],
+            vec![closer.clone()],
+            vec![closer.clone()],
+        ];
+        assert_eq!(
+            lock_on(&Closest, &ticks),
+            [Some(0), Some(0), None, None, None, Some(1)]
+        );
+    }
+
+    #[test]
+    fn lock_lets_go_of_targets_that_leave_or_are_ruled_out() {
+        let a = named(0, "a", 0.0, -10.0);
+        let b = named(1, "b", 0.0, -11.0);
+        let fov = from_spec("closest,fov=30").unwrap();
+        let range = from_spec("closest,range=12").unwrap();
+
+        let cases: Vec<(&str, &dyn TargetSelector, Player)> = vec![
+            // Leaving, or dropping out of sight, takes the player out of the candidates.
+            ("left", &Closest, b.clone()),
+            ("out of the fov", fov.as_ref(), named(0, "a", 10.0, 0.0)),
+            ("out of range", range.as_ref(), named(0, "a", 0.0, -14.0)),
+            // Someone new in the same slot is a different target.
+            ("slot reused", &Closest, named(0, "new", 0.0, -1.0)),
+        ];
+        for (what, selector, after) in cases {
+            let mut ticks = vec![vec![a.clone(), b.clone()]];
+            let after = if what == "left" {
+                vec![b.clone()]
+            } else {
+                vec![after, b.clone()]
+            };
+            for _ in 0..4 {
+                ticks.push(after.clone());
+            }
+            let picked = lock_on(selector, &ticks);
+            assert_eq!(picked[0], Some(0), "{}", what);
+            assert_eq!(picked[1..4], [None, None, None], "{}", what);
+            assert!(picked[4].is_some(), "{}", what);
+        }
+    }
+
+    #[test]
+    fn release_skips_the_switch_delay() {
+        let (me, now) = (me(), Instant::now());
+        let players = vec![named(0, "a", 0.0, -10.0)];
+        let mut lock = Lock::new(DELAY);
+        assert!(lock.select(&Closest, &me, &players, now).is_some());
+        assert!(lock.select(&Closest, &me, &[], now).is_none());
+        lock.release();
+        assert!(lock.select(&Closest, &me, &players, now).is_some());
+    }
+}
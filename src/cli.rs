@@ -0,0 +1,391 @@
+//! Command-line parsing
+//!
+//! Arguments are a subcommand followed by its positional arguments and options, in any order.
+//! Running with no arguments at all is the same as `run`.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::{self, Config};
+use crate::output::Format;
+use crate::proc;
+
+pub const USAGE: &str = "\
+Usage: ac-trainer [COMMAND] [OPTIONS]
+
+Commands:
+  run                      Wait for the game and run the enabled features (default)
+  table                    Only print the player and item tables
+  scan PATTERN             Find a byte pattern such as \"29 7b ?? 8b c7\" in the game's code
+  dump [MODULE|FILE]       Describe a loaded module, or a PE or .cgz map file on disk
+  patch enable|disable NAME
+                           Apply or undo a code patch (godmode) in the running game
+  selftest                 Check the trainer's offsets against the running game; fails
+                           straight away if it isn't running
+  features                 List the features and the settings each one reads
+  help                     Show this message
+
+Options:
+  --process NAME           Name of the game process [default: ac_client.exe]
+  --pid PID                Attach to this process instead of looking it up by name
+  --module NAME            Module to scan [default: the process name]
+  --config FILE            Configuration file [default: ac-trainer.ini]
+  --enable FEATURES        Turn on features, given as a comma-separated list
+  --disable FEATURES       Turn off features, given as a comma-separated list
+  --tick FEATURE=MS        How often a feature runs, in milliseconds
+  --format FORMAT          How to print the tables: table, json, csv or dashboard
+                           [default: table]
+  -h, --help               Show this message
+
+Features: aimbot, triggerbot, godmode, table, events. All are on by default.
+Features with a tick: aimbot, triggerbot, table.
+
+Options given here take precedence over the configuration file.";
+
+pub const DEFAULT_PROCESS: &str = "ac_client.exe";
+
+pub enum Command {
+    Help,
+    Run(Options),
+    Scan {
+        options: Options,
+        pattern: String,
+        module: Option<String>,
+    },
+    Dump {
+        options: Options,
+        what: Option<String>,
+    },
+    Patch {
+        options: Options,
+        name: String,
+        enable: bool,
+    },
+    SelfTest(Options),
+    Features,
+}
+
+/// A setting given on the command line, overriding the configuration file
+#[derive(Clone, Debug)]
+pub enum Override {
+    Feature(String, bool),
+    Tick(String, Duration),
+    Format(Format),
+}
+
+impl Override {
+    pub fn apply(&self, config: &mut Config) -> Result<(), String> {
+        match self {
+            Override::Feature(name, enabled) => config.features.set(name, *enabled),
+            Override::Tick(feature, tick) => config.set_tick(feature, *tick),
+            Override::Format(format) => {
+                config.output.format = *format;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub process: String,
+    pub pid: Option<proc::Pid>,
+    pub config: Option<PathBuf>,
+    pub overrides: Vec<Override>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            process: String::from(DEFAULT_PROCESS),
+            pid: None,
+            config: None,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    pub fn config_path(&self) -> PathBuf {
+        self.config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(config::DEFAULT_PATH))
+    }
+
+    /// Apply the command-line overrides on top of a configuration
+    pub fn apply(&self, config: &mut Config) {
+        for setting in &self.overrides {
+            // Every override was tried out on a configuration when parsing the arguments.
+            setting
+                .apply(config)
+                .expect("invalid command-line override");
+        }
+    }
+
+    fn push(&mut self, setting: Override) -> Result<(), String> {
+        setting.apply(&mut Config::default())?;
+        self.overrides.push(setting);
+        Ok(())
+    }
+}
+
+/// Parse the arguments, not including the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+
+    // Options may come straight after the program name, in which case the command is `run`.
+    let command = match args.peek() {
+        Some(arg) if !arg.starts_with('-') => args.next().unwrap_or_default(),
+        _ => String::from("run"),
+    };
+
+    let mut options = Options::default();
+    if command == "table" {
+        for feature in &["aimbot", "triggerbot", "godmode"] {
+            options.push(Override::Feature(String::from(*feature), false))?;
+        }
+    }
+
+    let mut module = None;
+    let mut positional = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--process" => options.process = value(&mut args, &arg)?,
+            "--pid" => {
+                let pid = value(&mut args, &arg)?;
+                let pid = pid.parse().map_err(|_| format!("invalid PID: {}", pid))?;
+                options.pid = Some(pid);
+            }
+            "--module" => module = Some(value(&mut args, &arg)?),
+            "--config" => options.config = Some(PathBuf::from(value(&mut args, &arg)?)),
+            "--enable" | "--disable" => {
+                for feature in value(&mut args, &arg)?.split(',') {
+                    let feature = String::from(feature.trim());
+                    options.push(Override::Feature(feature, arg == "--enable"))?;
+                }
+            }
+            "--tick" => {
+                let tick = value(&mut args, &arg)?;
+                let (feature, ms) = split_assignment(&tick)?;
+                let ms = ms
+                    .parse()
+                    .map_err(|_| format!("invalid tick for {}: {}", feature, ms))?;
+                let tick = Duration::from_millis(ms);
+                options.push(Override::Tick(String::from(feature), tick))?;
+            }
+            "--format" => {
+                let format = Format::from_name(&value(&mut args, &arg)?)?;
+                options.push(Override::Format(format))?;
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let command = match command.as_str() {
+        "help" => Command::Help,
+        "run" | "table" => Command::Run(options),
+        "scan" => Command::Scan {
+            options,
+            pattern: positional
+                .next()
+                .ok_or_else(|| String::from("scan needs a pattern"))?,
+            module,
+        },
+        "dump" => Command::Dump {
+            options,
+            what: positional.next(),
+        },
+        "patch" => {
+            let enable = match positional.next().as_deref() {
+                Some("enable") => true,
+                Some("disable") => false,
+                _ => return Err(String::from("patch needs either enable or disable")),
+            };
+            let name = positional
+                .next()
+                .ok_or_else(|| String::from("patch needs the name of a patch"))?;
+            Command::Patch {
+                options,
+                name,
+                enable,
+            }
+        }
+        "selftest" => Command::SelfTest(options),
+        "features" => Command::Features,
+        _ => return Err(format!("unknown command: {}", command)),
+    };
+
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument: {}", extra));
+    }
+
+    Ok(command)
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn split_assignment(text: &str) -> Result<(&str, &str), String> {
+    let mut kv = text.splitn(2, '=');
+    let key = kv.next().unwrap_or_default().trim();
+    let value = kv
+        .next()
+        .ok_or_else(|| format!("expected NAME=VALUE, got {}", text))?
+        .trim();
+    Ok((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_line(line: &str) -> Result<Command, String> {
+        parse(line.split_whitespace().map(String::from))
+    }
+
+    /// The configuration a command's options make out of the default one
+    fn configured(options: &Options) -> Config {
+        let mut config = Config::default();
+        options.apply(&mut config);
+        config
+    }
+
+    #[test]
+    fn run_is_the_default() {
+        for line in &["", "run", "--pid 42"] {
+            match parse_line(line) {
+                Ok(Command::Run(options)) => assert!(configured(&options).features.godmode),
+                _ => panic!("{:?} didn't parse as run", line),
+            }
+        }
+    }
+
+    #[test]
+    fn table_turns_off_everything_that_touches_the_game() {
+        let options = match parse_line("table --enable godmode") {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("table didn't parse as run"),
+        };
+        let features = configured(&options).features;
+        assert!(!features.aimbot && !features.triggerbot);
+        assert!(features.table && features.events);
+        // Options come after the command's own overrides, so they win.
+        assert!(features.godmode);
+    }
+
+    #[test]
+    fn process_options() {
+        let options = match parse_line("run --pid 1234 --process game.exe --config my.ini") {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("didn't parse as run"),
+        };
+        assert_eq!(options.pid, Some(1234));
+        assert_eq!(options.process, "game.exe");
+        assert_eq!(options.config_path(), PathBuf::from("my.ini"));
+        assert_eq!(
+            Options::default().config_path(),
+            PathBuf::from(config::DEFAULT_PATH)
+        );
+        assert_eq!(Options::default().process, DEFAULT_PROCESS);
+    }
+
+    #[test]
+    fn tick_and_format() {
+        let options = match parse_line("--tick aimbot=5 --tick table=250 --format json") {
+            Ok(Command::Run(options)) => options,
+            _ => panic!("didn't parse as run"),
+        };
+        let config = configured(&options);
+        assert_eq!(config.aimbot.tick, Duration::from_millis(5));
+        assert_eq!(config.output.interval, Duration::from_millis(250));
+        assert_eq!(config.output.format, Format::Json);
+    }
+
+    #[test]
+    fn patch() {
+        match parse_line("patch enable godmode") {
+            Ok(Command::Patch { name, enable, .. }) => {
+                assert_eq!((name.as_str(), enable), ("godmode", true))
+            }
+            _ => panic!("patch enable didn't parse"),
+        }
+        match parse_line("patch disable godmode --pid 7") {
+            Ok(Command::Patch {
+                name,
+                enable,
+                options,
+            }) => {
+                assert_eq!(
+                    (name.as_str(), enable, options.pid),
+                    ("godmode", false, Some(7))
+                )
+            }
+            _ => panic!("patch disable didn't parse"),
+        }
+    }
+
+    #[test]
+    fn scan_and_dump_positionals() {
+        // A pattern has spaces in it, so it arrives as one argument.
+        let args = ["scan", "29 7b ?? 8b", "--module", "opengl32.dll"];
+        match parse(args.iter().map(|&arg| String::from(arg))) {
+            Ok(Command::Scan {
+                pattern, module, ..
+            }) => {
+                assert_eq!(pattern, "29 7b ?? 8b");
+                assert_eq!(module.as_deref(), Some("opengl32.dll"));
+            }
+            _ => panic!("scan didn't parse"),
+        }
+        match parse_line("dump maps/ac_depot.cgz") {
+            Ok(Command::Dump { what, .. }) => {
+                assert_eq!(what.as_deref(), Some("maps/ac_depot.cgz"))
+            }
+            _ => panic!("dump didn't parse"),
+        }
+        assert!(matches!(
+            parse_line("dump"),
+            Ok(Command::Dump { what: None, .. })
+        ));
+    }
+
+    #[test]
+    fn help() {
+        for line in &["help", "-h", "--help", "scan --help", "--pid 5 --help"] {
+            assert!(matches!(parse_line(line), Ok(Command::Help)), "{:?}", line);
+        }
+        assert!(matches!(parse_line("features"), Ok(Command::Features)));
+        assert!(matches!(parse_line("selftest"), Ok(Command::SelfTest(_))));
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("run --bogus", "unknown option: --bogus"),
+            ("launch", "unknown command: launch"),
+            ("run --pid", "--pid needs a value"),
+            ("run --pid abc", "invalid PID: abc"),
+            ("run --format", "--format needs a value"),
+            ("run --format xml", "unknown output format: xml"),
+            ("run --tick aimbot", "expected NAME=VALUE, got aimbot"),
+            ("run --tick aimbot=fast", "invalid tick for aimbot: fast"),
+            ("run --tick godmode=5", "feature godmode has no tick"),
+            ("run --enable wallhack", "unknown feature: wallhack"),
+            ("scan", "scan needs a pattern"),
+            ("patch", "patch needs either enable or disable"),
+            ("patch enable", "patch needs the name of a patch"),
+            ("run extra", "unexpected argument: extra"),
+        ];
+        for &(line, expected) in &cases {
+            match parse_line(line) {
+                Err(err) => assert_eq!(err, expected, "{:?}", line),
+                Ok(_) => panic!("{:?} parsed", line),
+            }
+        }
+    }
+}
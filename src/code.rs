@@ -1,7 +1,18 @@
-use crate::proc;
+use crate::proc::{self, ProcessMemory};
 
 const NOP: u8 = 0x90;
 const JMP_LEN: usize = 5;
+const JMP: u8 = 0xe9;
+
+/// The names of the patches that `by_name` knows about
+pub const PATCHES: &[&str] = &["godmode"];
+
+pub fn by_name(name: &str) -> Option<InjectionSpec> {
+    match name {
+        "godmode" => Some(godmode()),
+        _ => None,
+    }
+}
 
 pub fn godmode() -> InjectionSpec {
     InjectionSpec {
@@ -30,12 +41,38 @@ pub struct InjectionSpec {
 }
 
 impl InjectionSpec {
-    pub fn inject(self, handle: proc::Handle) -> Result<Injection, String> {
+    /// Check what's at the patched address: `Ok(false)` if it's the original code, `Ok(true)` if
+    /// it's been detoured, and an error if it's neither, e.g. because the game version differs.
+    pub fn is_applied<M: ProcessMemory>(&self, mut memory: M) -> Result<bool, String> {
+        let current = memory.read_bytes(self.original_addr, self.original_code.len())?;
+        if current == self.original_code {
+            Ok(false)
+        } else if current.first() == Some(&JMP) {
+            Ok(true)
+        } else {
+            Err(format!(
+                "unexpected code at {:#x}; is this the right game version?",
+                self.original_addr
+            ))
+        }
+    }
+
+    /// Put the original code back, undoing a patch applied by an earlier run
+    ///
+    /// The injected code stays allocated in the game; without the detour, nothing runs it.
+    pub fn restore<M: ProcessMemory>(&self, mut memory: M) -> Result<(), String> {
+        memory.write_code(self.original_addr, &self.original_code)
+    }
+
+    pub fn inject<M: ProcessMemory>(self, mut memory: M) -> Result<Injection, String> {
         if self.original_code.len() < JMP_LEN {
-            return Err(format!("original_code must be at least {} bytes long", JMP_LEN))
+            return Err(format!(
+                "original_code must be at least {} bytes long",
+                JMP_LEN
+            ));
         }
 
-        let new_code_addr = proc::alloc_ex(handle, self.new_code.len())?;
+        let new_code_addr = memory.alloc(self.new_code.len())?;
 
         let mut new_code = self.new_code;
         new_code.extend_from_slice(
@@ -45,7 +82,7 @@ impl InjectionSpec {
             )[..],
         );
 
-        proc::write(handle, new_code_addr, &new_code)?;
+        memory.write_bytes(new_code_addr, &new_code)?;
 
         Ok(Injection {
             original_addr: self.original_addr,
@@ -62,19 +99,19 @@ pub struct Injection {
 }
 
 impl Injection {
-    pub fn enable(&self, handle: proc::Handle) -> Result<(), String> {
+    pub fn enable<M: ProcessMemory>(&self, mut memory: M) -> Result<(), String> {
         let mut detour = vec![NOP; self.original_code.len()];
         detour[..JMP_LEN].clone_from_slice(&jmp(self.original_addr, self.new_code_addr)[..]);
-        proc::write_protected(handle, self.original_addr, &detour[..])
+        memory.write_code(self.original_addr, &detour[..])
     }
 
-    pub fn disable(&self, handle: proc::Handle) -> Result<(), String> {
-        proc::write_protected(handle, self.original_addr, &self.original_code)
+    pub fn disable<M: ProcessMemory>(&self, mut memory: M) -> Result<(), String> {
+        memory.write_code(self.original_addr, &self.original_code)
     }
 }
 
 fn jmp(src: proc::Address, dst: proc::Address) -> [u8; JMP_LEN] {
     let offset = dst as i32 - (src as i32 + JMP_LEN as i32);
     let offset: [u8; 4] = unsafe { std::mem::transmute(offset) };
-    [0xe9, offset[0], offset[1], offset[2], offset[3]]
+    [JMP, offset[0], offset[1], offset[2], offset[3]]
 }
@@ -0,0 +1,497 @@
+//! The configuration file
+//!
+//! The file is INI-style: `[section]` headers followed by `key = value` lines, with `#` or `;`
+//! starting a comment. Every key is optional and falls back to its built-in default, so an empty
+//! file is a valid configuration. For example:
+//!
+//! ```ini
+//! [features]
+//! godmode = false
+//!
+//! [hotkeys]
+//! aimbot = mouse2 hold
+//!
+//! [aimbot]
+//! target = weighted,distance=1,angle=4,fov=45
+//! max_degrees_per_tick = 4
+//! ```
+//!
+//! The file is watched for changes and re-read while the trainer runs, so settings can be tuned
+//! without restarting it.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::aimbot::aimpoint::AimPoint;
+use crate::aimbot::smooth::Easing;
+use crate::aimbot::target;
+use crate::input::{Key, Mode};
+use crate::output::Format;
+
+/// Where the configuration is read from unless another file is given
+pub const DEFAULT_PATH: &str = "ac-trainer.ini";
+
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub features: Features,
+    pub aimbot: Aimbot,
+    pub triggerbot: Triggerbot,
+    pub output: Output,
+    pub radar: Radar,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+    pub aimbot: bool,
+    pub triggerbot: bool,
+    pub godmode: bool,
+    pub table: bool,
+    pub events: bool,
+}
+
+/// A key and how it switches a feature on and off
+#[derive(Clone, Copy, Debug)]
+pub struct Hotkey {
+    pub key: Key,
+    pub mode: Mode,
+}
+
+#[derive(Clone, Debug)]
+pub struct Aimbot {
+    pub hotkey: Hotkey,
+    /// How often the aim is updated while active
+    pub tick: Duration,
+    /// How often to check the hotkey while inactive
+    pub idle_tick: Duration,
+    /// A target selector description, as understood by `target::from_spec`
+    pub target: String,
+    pub aim_point: AimPoint,
+    pub max_degrees_per_tick: f32,
+    pub easing: Easing,
+    pub ramp: f32,
+    pub jitter: f32,
+    pub visible_only: bool,
+    pub prediction: bool,
+    pub latency: Duration,
+    pub switch_delay: Duration,
+    pub recoil_compensation: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Triggerbot {
+    pub hotkey: Hotkey,
+    pub tick: Duration,
+    pub reaction: Duration,
+    pub burst: Duration,
+}
+
+#[derive(Clone, Debug)]
+pub struct Output {
+    /// How often the tables are printed
+    pub interval: Duration,
+    /// Whether the tables include the items on the map
+    pub items: bool,
+    pub format: Format,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Radar {
+    /// How far in the dashboard's radar is zoomed; 2 shows half as far around as 1
+    pub zoom: f32,
+}
+
+impl Default for Features {
+    fn default() -> Features {
+        Features {
+            aimbot: true,
+            triggerbot: true,
+            godmode: true,
+            table: true,
+            events: true,
+        }
+    }
+}
+
+impl Features {
+    pub fn set(&mut self, name: &str, enabled: bool) -> Result<(), String> {
+        match name {
+            "aimbot" => self.aimbot = enabled,
+            "triggerbot" => self.triggerbot = enabled,
+            "godmode" => self.godmode = enabled,
+            "table" => self.table = enabled,
+            "events" => self.events = enabled,
+            _ => return Err(format!("unknown feature: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+impl Hotkey {
+    /// Parse a key name optionally followed by a mode, e.g. `capslock toggle` or `mouse2 hold`
+    ///
+    /// Without a mode, keys toggle.
+    pub fn parse(text: &str) -> Result<Hotkey, String> {
+        let mut words = text.split_whitespace();
+        let key = Key::from_name(words.next().unwrap_or_default())?;
+        let mode = match words.next() {
+            Some(mode) => Mode::from_name(mode)?,
+            None => Mode::Toggle,
+        };
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected {} after hotkey", extra));
+        }
+        Ok(Hotkey { key, mode })
+    }
+}
+
+impl Default for Aimbot {
+    fn default() -> Aimbot {
+        Aimbot {
+            hotkey: Hotkey {
+                key: Key::CapsLock,
+                mode: Mode::Toggle,
+            },
+            tick: Duration::from_millis(10),
+            idle_tick: Duration::from_millis(100),
+            target: String::from(target::DEFAULT_SPEC),
+            aim_point: AimPoint::Head,
+            max_degrees_per_tick: 6.0,
+            easing: Easing::Smoothstep,
+            ramp: 20.0,
+            jitter: 0.0,
+            visible_only: true,
+            prediction: true,
+            latency: Duration::from_millis(50),
+            switch_delay: Duration::from_millis(250),
+            recoil_compensation: 1.0,
+        }
+    }
+}
+
+impl Default for Triggerbot {
+    fn default() -> Triggerbot {
+        Triggerbot {
+            hotkey: Hotkey {
+                key: Key::MouseBack,
+                mode: Mode::Hold,
+            },
+            tick: Duration::from_millis(5),
+            reaction: Duration::from_millis(80),
+            burst: Duration::from_millis(150),
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Output {
+        Output {
+            interval: Duration::from_millis(1000),
+            items: true,
+            format: Format::Table,
+        }
+    }
+}
+
+impl Default for Radar {
+    fn default() -> Radar {
+        Radar { zoom: 1.0 }
+    }
+}
+
+impl Config {
+    pub fn read_file<P: AsRef<Path>>(path: P) -> Result<Config, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        Config::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = "";
+
+        for (index, line) in text.lines().enumerate() {
+            let line = match line.find(&['#', ';'][..]) {
+                Some(comment) => &line[..comment],
+                None => line,
+            }
+            .trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                section = line[1..line.len() - 1].trim();
+                continue;
+            }
+
+            let mut kv = line.splitn(2, '=');
+            let key = kv.next().unwrap_or_default().trim();
+            let result = match kv.next() {
+                Some(value) => config.set(section, key, value.trim()),
+                None => Err(format!("expected key = value, got {}", line)),
+            };
+            result.map_err(|err| format!("line {}: {}", index + 1, err))?;
+        }
+
+        Ok(config)
+    }
+
+    /// Set a single value, as if it had been given in the file
+    pub fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), String> {
+        match (section, key) {
+            ("features", _) => self.features.set(key, boolean(value)?)?,
+
+            ("hotkeys", "aimbot") => self.aimbot.hotkey = Hotkey::parse(value)?,
+            ("hotkeys", "triggerbot") => self.triggerbot.hotkey = Hotkey::parse(value)?,
+
+            ("aimbot", "tick") => self.aimbot.tick = millis(value)?,
+            ("aimbot", "idle_tick") => self.aimbot.idle_tick = millis(value)?,
+            ("aimbot", "target") => {
+                // Catch mistakes now rather than when the aimbot gets around to using it.
+                target::from_spec(value)?;
+                self.aimbot.target = String::from(value);
+            }
+            ("aimbot", "aim_point") => self.aimbot.aim_point = AimPoint::from_name(value)?,
+            ("aimbot", "max_degrees_per_tick") => self.aimbot.max_degrees_per_tick = number(value)?,
+            ("aimbot", "easing") => self.aimbot.easing = Easing::from_name(value)?,
+            ("aimbot", "ramp") => self.aimbot.ramp = number(value)?,
+            ("aimbot", "jitter") => self.aimbot.jitter = number(value)?,
+            ("aimbot", "visible_only") => self.aimbot.visible_only = boolean(value)?,
+            ("aimbot", "prediction") => self.aimbot.prediction = boolean(value)?,
+            ("aimbot", "latency") => self.aimbot.latency = millis(value)?,
+            ("aimbot", "switch_delay") => self.aimbot.switch_delay = millis(value)?,
+            ("aimbot", "recoil_compensation") => self.aimbot.recoil_compensation = number(value)?,
+
+            ("triggerbot", "tick") => self.triggerbot.tick = millis(value)?,
+            ("triggerbot", "reaction") => self.triggerbot.reaction = millis(value)?,
+            ("triggerbot", "burst") => self.triggerbot.burst = millis(value)?,
+
+            ("output", "interval") => self.output.interval = millis(value)?,
+            ("output", "items") => self.output.items = boolean(value)?,
+            ("output", "format") => self.output.format = Format::from_name(value)?,
+
+            ("radar", "zoom") => {
+                let zoom = number(value)?;
+                if !(zoom > 0.0 && zoom.is_finite()) {
+                    return Err(format!("expected a zoom above 0, got {}", value));
+                }
+                self.radar.zoom = zoom;
+            }
+
+            _ => return Err(format!("unknown setting {} in [{}]", key, section)),
+        }
+        Ok(())
+    }
+
+    /// Set how often a feature runs
+    pub fn set_tick(&mut self, feature: &str, tick: Duration) -> Result<(), String> {
+        match feature {
+            "aimbot" => self.aimbot.tick = tick,
+            "triggerbot" => self.triggerbot.tick = tick,
+            "table" => self.output.interval = tick,
+            _ => return Err(format!("feature {} has no tick", feature)),
+        }
+        Ok(())
+    }
+}
+
+fn boolean(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, got {}", value)),
+    }
+}
+
+fn number(value: &str) -> Result<f32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, got {}", value))
+}
+
+/// A duration, given in milliseconds
+fn millis(value: &str) -> Result<Duration, String> {
+    value
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("expected a number of milliseconds, got {}", value))
+}
+
+/// Notices when the configuration file changes, so it can be re-read
+pub struct Watcher {
+    path: PathBuf,
+    /// The modification time seen last, or `None` before the first poll
+    modified: Option<Option<SystemTime>>,
+}
+
+impl Watcher {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Watcher {
+        Watcher {
+            path: path.into(),
+            modified: None,
+        }
+    }
+
+    /// Read the file if it changed since the last poll, which the first poll always counts as
+    ///
+    /// A missing file gives the default configuration, so deleting the file resets everything.
+    pub fn poll(&mut self) -> Option<Result<Config, String>> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        if self.modified == Some(modified) {
+            return None;
+        }
+        self.modified = Some(modified);
+
+        match modified {
+            Some(_) => Some(Config::read_file(&self.path)),
+            None => Some(Ok(Config::default())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::File;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn sections_and_comments() {
+        let config = Config::parse(
+            "
+            # A comment
+            [features]
+            godmode = false   ; trailing comment
+            [ aimbot ]
+            target = weighted,distance=1,angle=4
+            max_degrees_per_tick=4
+            latency = 20 # ms
+
+            [radar]
+            zoom = 2
+            ",
+        )
+        .unwrap();
+
+        assert!(!config.features.godmode);
+        assert!(config.features.aimbot);
+        assert_eq!(config.aimbot.target, "weighted,distance=1,angle=4");
+        assert_eq!(config.aimbot.max_degrees_per_tick, 4.0);
+        assert_eq!(config.aimbot.latency, Duration::from_millis(20));
+        assert_eq!(config.radar.zoom, 2.0);
+        // Everything left out keeps its default.
+        assert_eq!(config.triggerbot.reaction, Duration::from_millis(80));
+    }
+
+    #[test]
+    fn an_empty_file_is_the_default() {
+        let config = Config::parse("\n# nothing here\n").unwrap();
+        assert_eq!(config.aimbot.target, Config::default().aimbot.target);
+        assert_eq!(config.output.interval, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let cases = [
+            (
+                "[features]\ngodmode = maybe",
+                "line 2: expected true or false, got maybe",
+            ),
+            (
+                "[aimbot]\n\nramp = steep",
+                "line 3: expected a number, got steep",
+            ),
+            (
+                "[aimbot]\ntick = -5",
+                "line 2: expected a number of milliseconds, got -5",
+            ),
+            (
+                "[radar]\nzoom = 0",
+                "line 2: expected a zoom above 0, got 0",
+            ),
+            (
+                "[features]\nwallhack = on",
+                "line 2: unknown feature: wallhack",
+            ),
+            (
+                "[aimbot]\nspeed = 4",
+                "line 2: unknown setting speed in [aimbot]",
+            ),
+            (
+                "[sound]\nvolume = 4",
+                "line 2: unknown setting volume in [sound]",
+            ),
+            ("zoom = 2", "line 1: unknown setting zoom in []"),
+            (
+                "[aimbot]\njitter",
+                "line 2: expected key = value, got jitter",
+            ),
+            ("[hotkeys]\naimbot = f13", "line 2: unknown key: f13"),
+            (
+                "[aimbot]\ntarget = nobody",
+                "line 2: unknown target strategy: nobody",
+            ),
+        ];
+        for &(text, expected) in &cases {
+            assert_eq!(Config::parse(text).unwrap_err(), expected, "{:?}", text);
+        }
+    }
+
+    /// A configuration file of its own for each test, removed once done
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> TempFile {
+            let name = format!("ac-trainer-{}-{}.ini", std::process::id(), name);
+            TempFile(std::env::temp_dir().join(name))
+        }
+
+        /// Write the file, with a modification time some seconds after the epoch so that every
+        /// write is seen as a change no matter how coarse the file system's clock is
+        fn write(&self, text: &str, seconds: u64) {
+            std::fs::write(&self.0, text).unwrap();
+            let file = File::options().write(true).open(&self.0).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+                .unwrap();
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn watcher_reads_each_change_once() {
+        let file = TempFile::new("watcher");
+        let mut watcher = Watcher::new(&file.0);
+
+        // No file is the default configuration, once.
+        assert!(matches!(watcher.poll(), Some(Ok(_))));
+        assert!(watcher.poll().is_none());
+
+        file.write("[radar]\nzoom = 2\n", 1);
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.radar.zoom, 2.0);
+        assert!(watcher.poll().is_none());
+
+        file.write("[radar]\nzoom = far\n", 2);
+        let err = watcher.poll().unwrap().unwrap_err();
+        assert!(
+            err.ends_with("line 2: expected a number, got far"),
+            "{}",
+            err
+        );
+        assert!(watcher.poll().is_none());
+
+        std::fs::remove_file(&file.0).unwrap();
+        let config = watcher.poll().unwrap().unwrap();
+        assert_eq!(config.radar.zoom, 1.0);
+    }
+}
@@ -0,0 +1,372 @@
+//! A full-screen view of the game that redraws in place, and takes keystrokes to switch features
+//!
+//! The dashboard is the `dashboard` output format of the player table. It draws with plain ANSI
+//! escape sequences on the console's alternate screen, which Windows 10 and later (and Wine)
+//! understand once virtual terminal processing is switched on.
+
+pub mod radar;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::config::{self, Config, Features};
+use crate::entities::Player;
+use crate::events::Event;
+use crate::game;
+use crate::scheduler::WorldSnapshot;
+use crate::winapi;
+use crate::winapi_error;
+
+/// How often the dashboard is redrawn and checked for keystrokes, at most
+pub const TICK: Duration = Duration::from_millis(100);
+
+/// How many of the most recent events are shown
+const EVENT_LINES: usize = 8;
+
+/// The features that can be switched from the dashboard, by the key in front of each
+const TOGGLES: &[(char, &str)] = &[
+    ('1', "aimbot"),
+    ('2', "triggerbot"),
+    ('3', "godmode"),
+    ('4', "events"),
+];
+
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+const HOME: &str = "\x1b[H";
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+
+const BOLD: &str = "1";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const BLUE: &str = "34";
+const GREY: &str = "90";
+
+/// What the features tell the dashboard, and what the dashboard tells the trainer
+///
+/// Clones share the same board.
+#[derive(Clone, Default)]
+pub struct Board(Rc<RefCell<BoardState>>);
+
+#[derive(Default)]
+struct BoardState {
+    aimbot_active: bool,
+    target: Option<String>,
+    /// Features switched from the dashboard, which take precedence over the configuration
+    toggles: Vec<(&'static str, bool)>,
+    /// Whether there are toggles that haven't been applied yet
+    toggled: bool,
+}
+
+impl Board {
+    /// Report whether the aimbot is active, and who it's locked onto
+    pub fn set_aimbot(&self, active: bool, target: Option<&str>) {
+        let mut state = self.0.borrow_mut();
+        state.aimbot_active = active;
+        state.target = target.map(String::from);
+    }
+
+    fn toggle(&self, feature: &'static str, enabled: bool) {
+        let mut state = self.0.borrow_mut();
+        state.toggles.retain(|&(name, _)| name != feature);
+        state.toggles.push((feature, enabled));
+        state.toggled = true;
+    }
+
+    /// Apply the features switched from the dashboard on top of a configuration, returning
+    /// whether any were switched since the last call
+    pub fn apply(&self, config: &mut Config) -> bool {
+        let mut state = self.0.borrow_mut();
+        for &(feature, enabled) in &state.toggles {
+            // Only the features in `TOGGLES` are ever switched.
+            config
+                .features
+                .set(feature, enabled)
+                .expect("toggled an unknown feature");
+        }
+        std::mem::replace(&mut state.toggled, false)
+    }
+}
+
+pub struct Dashboard {
+    terminal: Terminal,
+    events: VecDeque<String>,
+}
+
+impl Dashboard {
+    /// Take over the console, which fails if there isn't one, e.g. because output is redirected
+    pub fn open() -> Result<Dashboard, String> {
+        Ok(Dashboard {
+            terminal: Terminal::open()?,
+            events: VecDeque::new(),
+        })
+    }
+
+    /// Switch features for the keys pressed since the last call
+    pub fn handle_keys(&mut self, features: &Features, board: &Board) {
+        for key in self.terminal.keys() {
+            if let Some(&(_, feature)) = TOGGLES.iter().find(|&&(k, _)| k == key) {
+                board.toggle(feature, !enabled(features, feature));
+            }
+        }
+    }
+
+    pub fn record(&mut self, events: &[Event]) {
+        for event in events {
+            if self.events.len() == EVENT_LINES {
+                self.events.pop_front();
+            }
+            self.events.push_back(event.to_string());
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        features: &Features,
+        board: &Board,
+        radar: &config::Radar,
+    ) {
+        let mut frame = String::from(HOME);
+        let mut line = |text: &str| {
+            frame.push_str(text);
+            frame.push_str(CLEAR_LINE);
+            frame.push('\n');
+        };
+
+        let mode = if snapshot.mode.is_team_mode() {
+            "team game"
+        } else {
+            "free for all"
+        };
+        line(&format!(
+            "{}  {}, {} players",
+            paint(BOLD, "ac-trainer"),
+            mode,
+            snapshot.players().count() + 1
+        ));
+        line("");
+
+        let state = board.0.borrow();
+        for &(key, feature) in TOGGLES {
+            let on = enabled(features, feature);
+            let status = if feature == "aimbot" && on {
+                match (&state.target, state.aimbot_active) {
+                    (Some(target), true) => format!("locked onto {}", target),
+                    (None, true) => String::from("looking for a target"),
+                    (_, false) => String::from("waiting for its hotkey"),
+                }
+            } else {
+                String::new()
+            };
+            let switch = if on {
+                paint(GREEN, "on ")
+            } else {
+                paint(GREY, "off")
+            };
+            line(&format!("[{}] {:12}{}  {}", key, feature, switch, status));
+        }
+        line("");
+
+        line(&paint(
+            BOLD,
+            "Name              Team  Health  Armor  Distance",
+        ));
+        let me = &snapshot.me;
+        line(&player_row(me, me, snapshot, true));
+        for player in snapshot.players() {
+            line(&player_row(me, player, snapshot, false));
+        }
+        line("");
+
+        line(&format!(
+            "{}  {} units to the edge, {} enemy {} teammate {} dead {} item",
+            paint(BOLD, "Radar"),
+            radar::range(radar),
+            paint(RED, "x"),
+            paint(BLUE, "o"),
+            paint(GREY, "+"),
+            paint(YELLOW, "*"),
+        ));
+        for row in radar::draw(snapshot, radar) {
+            line(&row);
+        }
+        line("");
+
+        let available = snapshot.items.iter().filter(|item| item.spawned).count();
+        line(&format!(
+            "Items: {} available, {} respawning",
+            available,
+            snapshot.items.len() - available
+        ));
+        line("");
+
+        line(&paint(BOLD, "Recent events"));
+        for event in &self.events {
+            line(event);
+        }
+        line("");
+
+        line("Press 1-4 to switch features on and off, Ctrl+C to quit.");
+
+        frame.push_str(CLEAR_BELOW);
+        print!("{}", frame);
+        // There's no one to tell if the console went away.
+        let _ = std::io::stdout().flush();
+    }
+}
+
+fn player_row(me: &Player, player: &Player, snapshot: &WorldSnapshot, is_me: bool) -> String {
+    let (team, team_colour) = match player.team {
+        game::TEAM_CLA => ("CLA", RED),
+        game::TEAM_RVSF => ("RVSF", BLUE),
+        _ => ("spec", GREY),
+    };
+    // Without teams, the colours would only suggest sides that don't exist.
+    let team = if snapshot.mode.is_team_mode() {
+        paint(team_colour, &format!("{:4}", team))
+    } else {
+        format!("{:4}", team)
+    };
+
+    let health_colour = match player.health {
+        h if h <= 0 => GREY,
+        h if h < 30 => RED,
+        h if h < 70 => YELLOW,
+        _ => GREEN,
+    };
+    let health = paint(health_colour, &format!("{:<6}", player.health));
+
+    let name = format!("{:16}", player.name);
+    let name = if is_me { paint(BOLD, &name) } else { name };
+
+    let distance = if is_me {
+        String::new()
+    } else {
+        format!("{:.1}", me.position.distance(player.position))
+    };
+
+    format!(
+        "{}  {}  {}  {:<5}  {}",
+        name, team, health, player.armor, distance
+    )
+}
+
+fn enabled(features: &Features, feature: &str) -> bool {
+    match feature {
+        "aimbot" => features.aimbot,
+        "triggerbot" => features.triggerbot,
+        "godmode" => features.godmode,
+        "events" => features.events,
+        _ => false,
+    }
+}
+
+/// Wrap text in an ANSI colour or style
+fn paint(code: &str, text: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+/// The console, switched to the alternate screen and to reading keys as they're pressed
+///
+/// Dropping it puts the console back the way it was.
+struct Terminal {
+    input: winapi::HANDLE,
+    output: winapi::HANDLE,
+    input_mode: winapi::DWORD,
+    output_mode: winapi::DWORD,
+}
+
+impl Terminal {
+    fn open() -> Result<Terminal, String> {
+        let input = std_handle(winapi::STD_INPUT_HANDLE)?;
+        let output = std_handle(winapi::STD_OUTPUT_HANDLE)?;
+        let terminal = Terminal {
+            input,
+            output,
+            input_mode: console_mode(input)?,
+            output_mode: console_mode(output)?,
+        };
+
+        set_console_mode(
+            output,
+            terminal.output_mode
+                | winapi::ENABLE_PROCESSED_OUTPUT
+                | winapi::ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+        )?;
+        // Without line input, keys are available as soon as they're pressed rather than on Enter.
+        set_console_mode(
+            input,
+            terminal.input_mode & !(winapi::ENABLE_LINE_INPUT | winapi::ENABLE_ECHO_INPUT),
+        )?;
+
+        print!("{}", ENTER_SCREEN);
+        Ok(terminal)
+    }
+
+    /// The characters typed since the last call, without waiting for any
+    fn keys(&mut self) -> Vec<char> {
+        let mut keys = Vec::new();
+        loop {
+            let mut pending: winapi::DWORD = 0;
+            let ok = unsafe { winapi::GetNumberOfConsoleInputEvents(self.input, &mut pending) };
+            if ok == 0 || pending == 0 {
+                break;
+            }
+
+            let mut record: winapi::INPUT_RECORD = unsafe { std::mem::zeroed() };
+            let mut read: winapi::DWORD = 0;
+            let ok = unsafe { winapi::ReadConsoleInputA(self.input, &mut record, 1, &mut read) };
+            if ok == 0 || read == 0 {
+                break;
+            }
+
+            // Mouse, focus and resize events are skipped, as are keys without a character.
+            if record.EventType == winapi::KEY_EVENT && record.Event.bKeyDown != 0 {
+                // ReadConsoleInputA leaves the ASCII character in the low byte.
+                let c = record.Event.uChar as u8;
+                if c != 0 {
+                    keys.push(c as char);
+                }
+            }
+        }
+        keys
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("{}", LEAVE_SCREEN);
+        let _ = std::io::stdout().flush();
+        let _ = set_console_mode(self.input, self.input_mode);
+        let _ = set_console_mode(self.output, self.output_mode);
+    }
+}
+
+fn std_handle(which: winapi::DWORD) -> Result<winapi::HANDLE, String> {
+    let handle = unsafe { winapi::GetStdHandle(which) };
+    if handle.is_null() || handle == winapi::INVALID_HANDLE_VALUE {
+        return Err(format!("GetStdHandle error: {}", winapi_error::last()));
+    }
+    Ok(handle)
+}
+
+fn console_mode(handle: winapi::HANDLE) -> Result<winapi::DWORD, String> {
+    let mut mode: winapi::DWORD = 0;
+    if unsafe { winapi::GetConsoleMode(handle, &mut mode) } == 0 {
+        return Err(format!("GetConsoleMode error: {}", winapi_error::last()));
+    }
+    Ok(mode)
+}
+
+fn set_console_mode(handle: winapi::HANDLE, mode: winapi::DWORD) -> Result<(), String> {
+    if unsafe { winapi::SetConsoleMode(handle, mode) } == 0 {
+        return Err(format!("SetConsoleMode error: {}", winapi_error::last()));
+    }
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! A top-down map of who and what is around us, turned so that straight ahead is always up
+//!
+//! Console cells are about twice as tall as they're wide, so the radar takes two columns for every
+//! row to keep distances the same in every direction. Anything beyond the edge is left off.
+
+use crate::config;
+use crate::entities::Player;
+use crate::game;
+use crate::math::{Angles, Vec3};
+use crate::scheduler::WorldSnapshot;
+
+use super::{paint, BLUE, BOLD, GREY, RED, YELLOW};
+
+/// Rows from the centre to the top or bottom edge
+const HALF_HEIGHT: usize = 7;
+const HALF_WIDTH: usize = HALF_HEIGHT * 2;
+
+/// World units from the centre to the top edge at a zoom of 1
+const RANGE: f32 = 64.0;
+
+const EMPTY: char = '·';
+
+/// What's drawn in a cell, and in which colour
+type Marker = (char, &'static str);
+
+const ME: Marker = ('^', BOLD);
+const ENEMY: Marker = ('x', RED);
+const TEAMMATE: Marker = ('o', BLUE);
+const DEAD: Marker = ('+', GREY);
+const ITEM: Marker = ('*', YELLOW);
+
+/// How far it is from the centre of the radar to its top edge, in world units
+pub fn range(radar: &config::Radar) -> f32 {
+    RANGE / radar.zoom
+}
+
+/// The radar's lines, top to bottom
+pub fn draw(snapshot: &WorldSnapshot, radar: &config::Radar) -> Vec<String> {
+    let me = &snapshot.me;
+    let range = range(radar);
+    let mut grid = [[None; HALF_WIDTH * 2 + 1]; HALF_HEIGHT * 2 + 1];
+
+    // Later markers cover earlier ones, so what matters most goes last.
+    let mut mark = |position: Vec3, marker: Marker| {
+        if let Some((row, column)) = cell(me.position, me.view.yaw, position, range) {
+            grid[row][column] = Some(marker);
+        }
+    };
+    for item in snapshot.items.iter().filter(|item| item.spawned) {
+        mark(item.position, ITEM);
+    }
+    // Spectators aren't anywhere in particular.
+    let players: Vec<&Player> = snapshot.players().filter(|p| game::is_playing(p)).collect();
+    for player in players.iter().filter(|p| p.health <= 0) {
+        mark(player.position, DEAD);
+    }
+    for (hostile, marker) in [(false, TEAMMATE), (true, ENEMY)] {
+        for player in &players {
+            if player.health > 0 && snapshot.mode.hostile(me, player) == hostile {
+                mark(player.position, marker);
+            }
+        }
+    }
+    grid[HALF_HEIGHT][HALF_WIDTH] = Some(ME);
+
+    grid.iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| match cell {
+                    Some((c, colour)) => paint(colour, &c.to_string()),
+                    None => paint(GREY, &EMPTY.to_string()),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The row and column a position falls in, seen from `origin` looking along `yaw`, or `None` if
+/// it's off the radar
+pub fn cell(origin: Vec3, yaw: f32, position: Vec3, range: f32) -> Option<(usize, usize)> {
+    let forward = Angles::new(yaw, 0.0).direction();
+    let right = Angles::new(yaw + 90.0, 0.0).direction();
+    let delta = position - origin;
+
+    let up = (delta.dot(forward) / range * HALF_HEIGHT as f32).round();
+    let across = (delta.dot(right) / range * HALF_WIDTH as f32).round();
+    // NaN positions, from a bad read, land nowhere.
+    if !(up.is_finite() && across.is_finite())
+        || up.abs() > HALF_HEIGHT as f32
+        || across.abs() > HALF_WIDTH as f32
+    {
+        return None;
+    }
+    Some((
+        (HALF_HEIGHT as f32 - up) as usize,
+        (HALF_WIDTH as f32 + across) as usize,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn straight_ahead_is_up() {
+        let origin = Vec3::new(100.0, -40.0, 8.0);
+        let range = 64.0;
+        for yaw in (0..24).map(|i| i as f32 * 15.0) {
+            let centre = cell(origin, yaw, origin, range);
+            assert_eq!(centre, Some((HALF_HEIGHT, HALF_WIDTH)));
+            let at = |turn: f32, distance: f32| {
+                let offset = Angles::new(yaw + turn, 0.0).direction() * distance;
+                cell(origin, yaw, origin + offset, range)
+            };
+
+            // Three rows' worth ahead, which is six columns' worth to the side
+            let distance = range * 3.0 / HALF_HEIGHT as f32;
+            // Row numbers grow downwards, so straight ahead is a smaller row in the same column.
+            assert_eq!(
+                at(0.0, distance),
+                Some((HALF_HEIGHT - 3, HALF_WIDTH)),
+                "yaw {}",
+                yaw
+            );
+            assert_eq!(
+                at(90.0, distance),
+                Some((HALF_HEIGHT, HALF_WIDTH + 6)),
+                "yaw {}",
+                yaw
+            );
+            assert_eq!(at(180.0, range * 2.0), None, "yaw {}", yaw);
+        }
+    }
+
+    #[test]
+    fn bad_positions_are_left_off() {
+        let nowhere = Vec3::new(f32::NAN, 0.0, 0.0);
+        assert_eq!(cell(Vec3::ZERO, 0.0, nowhere, RANGE), None);
+    }
+}
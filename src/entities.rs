@@ -1,71 +1,360 @@
-use crate::proc::{self, Read};
+use crate::math::{Angles, Cylinder, Vec3};
+use crate::proc::{self, ProcessMemory, Read};
 
+#[derive(Clone, Debug, Default)]
 pub struct Player {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+    /// Where the player is, which the game keeps at eye level (physent `o`)
+    pub position: Vec3,
+    /// The position of the player's feet
+    pub feet: Vec3,
+    /// How far above their feet the player's eyes are, which is less while crouching
+    pub eye_height: f32,
+    /// How far the top of the player's head is above their eyes
+    pub above_eye: f32,
+    pub view: Angles,
+    pub crouching: bool,
     pub health: i32,
     pub armor: i32,
+    pub team: i32,
+    /// Index in the game's player list, or `None` for the local player, who isn't in it
+    pub slot: Option<usize>,
+    /// The name as it's stored in the game, up to its terminating NUL
+    pub name_raw: Vec<u8>,
+    /// The name decoded for display; see `decode_name`
     pub name: String,
 }
 
 pub const PLAYER_X_OFFSET: isize = 0x4;
 pub const PLAYER_Y_OFFSET: isize = 0x8;
 pub const PLAYER_Z_OFFSET: isize = 0xC;
+pub const PLAYER_FEET_X_OFFSET: isize = 0x34;
+pub const PLAYER_FEET_Y_OFFSET: isize = 0x38;
+pub const PLAYER_FEET_Z_OFFSET: isize = 0x3C;
 pub const PLAYER_YAW_OFFSET: isize = 0x40;
 pub const PLAYER_PITCH_OFFSET: isize = 0x44;
+pub const PLAYER_EYE_HEIGHT_OFFSET: isize = 0x5C;
+pub const PLAYER_ABOVE_EYE_OFFSET: isize = 0x64;
+pub const PLAYER_CROUCHING_OFFSET: isize = 0x6C;
+/// Set while the player holds down the fire button
+pub const PLAYER_ATTACKING_OFFSET: isize = 0x224;
 pub const PLAYER_HEALTH_OFFSET: isize = 0xF8;
 pub const PLAYER_ARMOR_OFFSET: isize = 0xFC;
+pub const PLAYER_TEAM_OFFSET: isize = 0x32C;
+pub const PLAYER_WEAPON_OFFSET: isize = 0x374;
 
 // The player name is a char[16], but we'll represent it as a native Rust string for ease of use.
 pub const PLAYER_NAME_OFFSET: isize = 0x225;
 pub const PLAYER_NAME_SIZE: usize = 16;
 
+/// The radius of the cylinder players collide (and get shot) as
+pub const PLAYER_RADIUS: f32 = 1.1;
+
+// AssaultCube colours text with a form feed followed by a single colour character.
+const COLOR_ESCAPE: u8 = 0x0c;
+
 impl Read for Player {
-    fn read(handle: proc::Handle, addr: proc::Address) -> Result<Player, String> {
-        let x = f32::read(handle, addr + PLAYER_X_OFFSET as u32)?;
-        let y = f32::read(handle, addr + PLAYER_Y_OFFSET as u32)?;
-        let z = f32::read(handle, addr + PLAYER_Z_OFFSET as u32)?;
-        let health = i32::read(handle, addr + PLAYER_HEALTH_OFFSET as u32)?;
-        let armor = i32::read(handle, addr + PLAYER_ARMOR_OFFSET as u32)?;
-
-        let name_bytes = proc::read(handle, addr + PLAYER_NAME_OFFSET as u32, PLAYER_NAME_SIZE)?
-            .iter()
-            .take_while(|&c| *c != 0)
-            .copied()
-            .collect::<Vec<u8>>();
-
-        let name = String::from_utf8(name_bytes)
-            .map_err(|_| String::from("invalid utf8 data in player name string"))?;
+    fn read<M: ProcessMemory>(mut memory: M, addr: proc::Address) -> Result<Player, String> {
+        let x = f32::read(&mut memory, addr + PLAYER_X_OFFSET as u32)?;
+        let y = f32::read(&mut memory, addr + PLAYER_Y_OFFSET as u32)?;
+        let z = f32::read(&mut memory, addr + PLAYER_Z_OFFSET as u32)?;
+        let feet_x = f32::read(&mut memory, addr + PLAYER_FEET_X_OFFSET as u32)?;
+        let feet_y = f32::read(&mut memory, addr + PLAYER_FEET_Y_OFFSET as u32)?;
+        let feet_z = f32::read(&mut memory, addr + PLAYER_FEET_Z_OFFSET as u32)?;
+        let eye_height = f32::read(&mut memory, addr + PLAYER_EYE_HEIGHT_OFFSET as u32)?;
+        let above_eye = f32::read(&mut memory, addr + PLAYER_ABOVE_EYE_OFFSET as u32)?;
+        let yaw = f32::read(&mut memory, addr + PLAYER_YAW_OFFSET as u32)?;
+        let pitch = f32::read(&mut memory, addr + PLAYER_PITCH_OFFSET as u32)?;
+        let crouching = bool::read(&mut memory, addr + PLAYER_CROUCHING_OFFSET as u32)?;
+        let health = i32::read(&mut memory, addr + PLAYER_HEALTH_OFFSET as u32)?;
+        let armor = i32::read(&mut memory, addr + PLAYER_ARMOR_OFFSET as u32)?;
+        let team = i32::read(&mut memory, addr + PLAYER_TEAM_OFFSET as u32)?;
+
+        let mut name_raw = memory.read_bytes(addr + PLAYER_NAME_OFFSET as u32, PLAYER_NAME_SIZE)?;
+        if let Some(end) = name_raw.iter().position(|&c| c == 0) {
+            name_raw.truncate(end);
+        }
+        let name = decode_name(&name_raw);
 
         Ok(Player {
-            x,
-            y,
-            z,
+            position: Vec3::new(x, y, z),
+            feet: Vec3::new(feet_x, feet_y, feet_z),
+            eye_height,
+            above_eye,
+            view: Angles::new(yaw, pitch),
+            crouching,
             health,
             armor,
+            team,
+            slot: None,
+            name_raw,
             name,
         })
     }
 }
 
-pub fn player_list(handle: proc::Handle) -> Result<Vec<Player>, String> {
-    let list_addr = proc::Address::read(handle, 0x50f4f8)?;
-    let list_length = u32::read(handle, 0x50f500)? as usize;
+impl Player {
+    /// The position the player sees (and shoots) from
+    pub fn eye(&self) -> Vec3 {
+        self.position
+    }
+
+    /// The space the player takes up, from their feet to the top of their head
+    pub fn hitbox(&self) -> Cylinder {
+        Cylinder {
+            base: self.feet,
+            radius: PLAYER_RADIUS,
+            height: self.eye_height + self.above_eye,
+        }
+    }
+}
+
+/// Decode a name from the game's 8-bit encoding, dropping colour codes and control characters
+///
+/// The game doesn't restrict names to ASCII or UTF-8; bytes above 0x7f are Latin-1, which maps
+/// directly onto the first 256 Unicode code points. Decoding therefore never fails. Latin-1 has
+/// control characters above 0x7f too (0x80 to 0x9f), which are dropped like the ASCII ones.
+pub fn decode_name(raw: &[u8]) -> String {
+    let mut name = String::with_capacity(raw.len());
+    let mut bytes = raw.iter();
+
+    while let Some(&c) = bytes.next() {
+        match c {
+            COLOR_ESCAPE => {
+                bytes.next();
+            }
+            c if (c as char).is_control() => {}
+            c => name.push(c as char),
+        }
+    }
+
+    name
+}
+
+/// Far more players than a server holds, to catch a garbage length read in the middle of a map
+/// change
+pub const MAX_PLAYERS: usize = 256;
+
+/// Read the player list, keeping each player at its index (slot) in the game's entity list
+pub fn player_slots<M: ProcessMemory>(mut memory: M) -> Result<Vec<Option<Player>>, String> {
+    let list_addr = proc::Address::read(&mut memory, 0x50f4f8)?;
+    let list_length = u32::read(&mut memory, 0x50f500)? as usize;
+    if list_length > MAX_PLAYERS {
+        return Err(format!("implausible player count: {}", list_length));
+    }
     let mut list = Vec::with_capacity(list_length);
 
     for index in 0..list_length {
-        let player_addr = proc::Address::read(handle, list_addr + (index as u32 * 0x4))?;
+        let player_addr = proc::Address::read(&mut memory, list_addr + (index as u32 * 0x4))?;
 
         // When entities are removed, their entity list pointer is set to null, but the remaining
         // entities are not moved.
         if player_addr == 0 {
+            list.push(None);
             continue;
         }
 
-        let player = Player::read(handle, player_addr)?;
+        // A player leaving while we read them takes their entity with them. That only concerns
+        // them, so treat the slot as empty rather than failing the whole list.
+        let player = Player::read(&mut memory, player_addr)
+            .ok()
+            .map(|mut player| {
+                player.slot = Some(index);
+                player
+            });
         list.push(player);
     }
 
     Ok(list)
 }
+
+/// A pickup (or flag) placed on the map
+#[derive(Clone)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub position: Vec3,
+    /// Whether the item can currently be picked up, as opposed to waiting to respawn
+    ///
+    /// That's all a client knows: the server keeps the respawn timers and only says when an item
+    /// comes back, so there's no time until respawn to read.
+    pub spawned: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ItemKind {
+    Clips,
+    Ammo,
+    Grenade,
+    Health,
+    Helmet,
+    Armor,
+    Akimbo,
+    Flag,
+}
+
+impl ItemKind {
+    /// Map the game's entity type to an item kind; entities that aren't items (lights, spawn
+    /// points, map models, ...) have none.
+    pub fn from_entity_type(kind: u8) -> Option<ItemKind> {
+        match kind {
+            3 => Some(ItemKind::Clips),
+            4 => Some(ItemKind::Ammo),
+            5 => Some(ItemKind::Grenade),
+            6 => Some(ItemKind::Health),
+            7 => Some(ItemKind::Helmet),
+            8 => Some(ItemKind::Armor),
+            9 => Some(ItemKind::Akimbo),
+            13 => Some(ItemKind::Flag),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ItemKind::Clips => "Pistol ammo",
+            ItemKind::Ammo => "Ammo box",
+            ItemKind::Grenade => "Grenades",
+            ItemKind::Health => "Health",
+            ItemKind::Helmet => "Helmet",
+            ItemKind::Armor => "Armor",
+            ItemKind::Akimbo => "Akimbo",
+            ItemKind::Flag => "Flag",
+        }
+    }
+}
+
+// Map entities are stored by value in the entity vector, each one looking like:
+//
+//     short x, y, z, attr1;
+//     uchar type, attr2, attr3, attr4;
+//     bool spawned;
+//     int lastmissed;
+//
+// `lastmissed` is when the local player last walked over the item without being able to take it,
+// which is only used to rate-limit a sound.
+pub const ENTITY_SIZE: usize = 0x14;
+pub const ENTITY_X_OFFSET: usize = 0x0;
+pub const ENTITY_Y_OFFSET: usize = 0x2;
+pub const ENTITY_Z_OFFSET: usize = 0x4;
+pub const ENTITY_TYPE_OFFSET: usize = 0x8;
+pub const ENTITY_SPAWNED_OFFSET: usize = 0xC;
+/// Everything before `spawned` comes from the map file and never changes while it's loaded
+const ENTITY_PERSISTENT_SIZE: usize = ENTITY_SPAWNED_OFFSET;
+
+/// Far more entities than any map has, to catch a garbage length read in the middle of a map change
+pub const MAX_ENTITIES: usize = 65536;
+
+/// Read every map entity as raw bytes, `ENTITY_SIZE` per entity
+/// Read the map's entities as they're laid out in the game, for `item_list` and `map_fingerprint`
+/// to pick apart
+pub fn entity_list<M: ProcessMemory>(mut memory: M) -> Result<Vec<u8>, String> {
+    let list_addr = proc::Address::read(&mut memory, 0x50f4e8)?;
+    let list_length = u32::read(&mut memory, 0x50f4f0)? as usize;
+    if list_length == 0 {
+        return Ok(Vec::new());
+    }
+    if list_length > MAX_ENTITIES {
+        return Err(format!("implausible entity count: {}", list_length));
+    }
+
+    // Entities are small and stored contiguously, so read them all at once rather than one field
+    // at a time.
+    let raw = memory.read_bytes(list_addr, list_length * ENTITY_SIZE)?;
+    if raw.len() != list_length * ENTITY_SIZE {
+        return Err(String::from("short read of entity list"));
+    }
+    Ok(raw)
+}
+
+/// The items among the entities read by `entity_list`
+pub fn item_list(entities: &[u8]) -> Vec<Item> {
+    entities
+        .chunks_exact(ENTITY_SIZE)
+        .filter_map(|entity| {
+            let kind = ItemKind::from_entity_type(entity[ENTITY_TYPE_OFFSET])?;
+            let coord = |offset: usize| i16::from_le_bytes([entity[offset], entity[offset + 1]]);
+            Some(Item {
+                kind,
+                position: Vec3::new(
+                    coord(ENTITY_X_OFFSET) as f32,
+                    coord(ENTITY_Y_OFFSET) as f32,
+                    coord(ENTITY_Z_OFFSET) as f32,
+                ),
+                spawned: entity[ENTITY_SPAWNED_OFFSET] != 0,
+            })
+        })
+        .collect()
+}
+
+/// A hash of where the map's entities are, which tells maps apart
+///
+/// Two maps of the same size can end up in the same memory, but they won't have the same entities.
+/// Unlike the geometry, the entity list is small enough to check every tick.
+pub fn map_fingerprint(entities: &[u8]) -> u64 {
+    // FNV-1a
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for entity in entities.chunks_exact(ENTITY_SIZE) {
+        for &byte in &entity[..ENTITY_PERSISTENT_SIZE] {
+            hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::fake::FakeMemory;
+
+    #[test]
+    fn decode_name_keeps_latin1() {
+        assert_eq!(decode_name(b"player"), "player");
+        assert_eq!(decode_name(b"J\xf6rg \xa9"), "Jörg ©");
+    }
+
+    #[test]
+    fn decode_name_drops_colours_and_controls() {
+        assert_eq!(decode_name(b"\x0c3red\x0c2green"), "redgreen");
+        assert_eq!(decode_name(b"a\tb\x7fc\x85d\x9fe"), "abcde");
+        // A colour escape at the very end has no colour to skip.
+        assert_eq!(decode_name(b"name\x0c"), "name");
+    }
+
+    #[test]
+    fn hitbox_runs_from_the_feet_to_the_top_of_the_head() {
+        let player = Player {
+            position: Vec3::new(1.0, 2.0, 7.0),
+            feet: Vec3::new(1.0, 2.0, 3.85),
+            eye_height: 3.15,
+            above_eye: 0.7,
+            crouching: true,
+            ..Player::default()
+        };
+        assert_eq!(player.eye(), player.position);
+
+        let hitbox = player.hitbox();
+        assert_eq!(hitbox.base, player.feet);
+        assert!((hitbox.height - 3.85).abs() < 1e-6);
+        // The head is hit, the space just above it isn't.
+        let forward = Vec3::new(1.0, 0.0, 0.0);
+        assert!(hitbox
+            .intersect(Vec3::new(-10.0, 2.0, 7.5), forward)
+            .is_some());
+        assert!(hitbox
+            .intersect(Vec3::new(-10.0, 2.0, 7.8), forward)
+            .is_none());
+    }
+
+    #[test]
+    fn read_keeps_the_raw_name() {
+        let addr = 0x1000;
+        let mut memory = FakeMemory::new();
+        memory.put(addr, &[0; 0x400]);
+        memory.put(addr + PLAYER_NAME_OFFSET as u32, b"\x0c3J\xf6rg\x85\0junk");
+        memory.put_i32(addr + PLAYER_HEALTH_OFFSET as u32, 100);
+
+        let player = Player::read(&mut memory, addr).unwrap();
+        assert_eq!(player.name_raw, b"\x0c3J\xf6rg\x85");
+        assert_eq!(player.name, "Jörg");
+        assert_eq!(player.health, 100);
+    }
+}
@@ -0,0 +1,436 @@
+//! Typed events describing what changed between successive reads of the player list
+
+use std::fmt;
+
+use crate::entities::Player;
+
+/// Moving further than this between two snapshots is considered a teleport rather than walking
+pub const TELEPORT_DISTANCE: f32 = 64.0;
+
+/// The health players start out with
+const FULL_HEALTH: i32 = 100;
+
+pub type Slot = usize;
+
+type Subscriber = Box<dyn FnMut(&Event) + Send>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Joined {
+        slot: Slot,
+        name: String,
+    },
+    Left {
+        slot: Slot,
+        name: String,
+    },
+    Died {
+        slot: Slot,
+        name: String,
+    },
+    Respawned {
+        slot: Slot,
+        name: String,
+    },
+    Damaged {
+        slot: Slot,
+        name: String,
+        amount: i32,
+    },
+    ArmorChanged {
+        slot: Slot,
+        name: String,
+        from: i32,
+        to: i32,
+    },
+    Renamed {
+        slot: Slot,
+        from: String,
+        to: String,
+    },
+    Teleported {
+        slot: Slot,
+        name: String,
+        distance: f32,
+    },
+}
+
+impl Event {
+    /// A short, stable name for the kind of event, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Joined { .. } => "joined",
+            Event::Left { .. } => "left",
+            Event::Died { .. } => "died",
+            Event::Respawned { .. } => "respawned",
+            Event::Damaged { .. } => "damaged",
+            Event::ArmorChanged { .. } => "armor_changed",
+            Event::Renamed { .. } => "renamed",
+            Event::Teleported { .. } => "teleported",
+        }
+    }
+
+    pub fn slot(&self) -> Slot {
+        match self {
+            Event::Joined { slot, .. }
+            | Event::Left { slot, .. }
+            | Event::Died { slot, .. }
+            | Event::Respawned { slot, .. }
+            | Event::Damaged { slot, .. }
+            | Event::ArmorChanged { slot, .. }
+            | Event::Renamed { slot, .. }
+            | Event::Teleported { slot, .. } => *slot,
+        }
+    }
+
+    /// The name of the player the event is about, which for a rename is their new name
+    pub fn name(&self) -> &str {
+        match self {
+            Event::Joined { name, .. }
+            | Event::Left { name, .. }
+            | Event::Died { name, .. }
+            | Event::Respawned { name, .. }
+            | Event::Damaged { name, .. }
+            | Event::ArmorChanged { name, .. }
+            | Event::Teleported { name, .. } => name,
+            Event::Renamed { to, .. } => to,
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Joined { slot, name } => write!(f, "[{}] {} joined", slot, name),
+            Event::Left { slot, name } => write!(f, "[{}] {} left", slot, name),
+            Event::Died { slot, name } => write!(f, "[{}] {} died", slot, name),
+            Event::Respawned { slot, name } => write!(f, "[{}] {} respawned", slot, name),
+            Event::Damaged { slot, name, amount } => {
+                write!(f, "[{}] {} took {} damage", slot, name, amount)
+            }
+            Event::ArmorChanged {
+                slot,
+                name,
+                from,
+                to,
+            } => write!(f, "[{}] {} armor {} -> {}", slot, name, from, to),
+            Event::Renamed { slot, from, to } => {
+                write!(f, "[{}] {} renamed to {}", slot, from, to)
+            }
+            Event::Teleported {
+                slot,
+                name,
+                distance,
+            } => write!(f, "[{}] {} teleported {:.1} units", slot, name, distance),
+        }
+    }
+}
+
+/// Compare two snapshots of the player slots and return the events that explain the difference
+///
+/// The game hands a slot that's been left to the next player to join, so a slot can change hands
+/// between two snapshots. That's told apart from a rename by the health: someone who just joined
+/// has full health, and a rename doesn't heal. A player leaving and someone joining at full health
+/// under a new name in a slot whose previous player also had full health still reads as a rename.
+pub fn diff(prev: &[Option<Player>], next: &[Option<Player>]) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    for slot in 0..prev.len().max(next.len()) {
+        let before = prev.get(slot).and_then(Option::as_ref);
+        let after = next.get(slot).and_then(Option::as_ref);
+
+        match (before, after) {
+            (None, Some(p)) => events.push(Event::Joined {
+                slot,
+                name: p.name.clone(),
+            }),
+            (Some(p), None) => events.push(Event::Left {
+                slot,
+                name: p.name.clone(),
+            }),
+            (Some(before), Some(after)) if reused(before, after) => {
+                events.push(Event::Left {
+                    slot,
+                    name: before.name.clone(),
+                });
+                events.push(Event::Joined {
+                    slot,
+                    name: after.name.clone(),
+                });
+            }
+            (Some(before), Some(after)) => diff_player(slot, before, after, &mut events),
+            (None, None) => {}
+        }
+    }
+
+    events
+}
+
+/// Whether a slot was taken over by someone else, rather than its player being renamed
+///
+/// Names are compared as stored, so that two names that differ only in colour count as different.
+fn reused(before: &Player, after: &Player) -> bool {
+    before.name_raw != after.name_raw && before.health != FULL_HEALTH && after.health == FULL_HEALTH
+}
+
+fn diff_player(slot: Slot, before: &Player, after: &Player, events: &mut Vec<Event>) {
+    let name = after.name.clone();
+
+    if before.name != after.name {
+        events.push(Event::Renamed {
+            slot,
+            from: before.name.clone(),
+            to: after.name.clone(),
+        });
+    }
+
+    let was_alive = before.health > 0;
+    let is_alive = after.health > 0;
+
+    if was_alive && after.health < before.health {
+        events.push(Event::Damaged {
+            slot,
+            name: name.clone(),
+            amount: before.health - after.health.max(0),
+        });
+    }
+
+    if before.armor != after.armor {
+        events.push(Event::ArmorChanged {
+            slot,
+            name: name.clone(),
+            from: before.armor,
+            to: after.armor,
+        });
+    }
+
+    match (was_alive, is_alive) {
+        (true, false) => events.push(Event::Died {
+            slot,
+            name: name.clone(),
+        }),
+        (false, true) => events.push(Event::Respawned {
+            slot,
+            name: name.clone(),
+        }),
+        _ => {}
+    }
+
+    // Respawning moves the player too, but that's already reported as a respawn.
+    let distance = before.position.distance(after.position);
+    if was_alive && is_alive && distance > TELEPORT_DISTANCE {
+        events.push(Event::Teleported {
+            slot,
+            name,
+            distance,
+        });
+    }
+}
+
+/// Keeps the previous snapshot around and hands the events of each new one to its subscribers
+pub struct Tracker {
+    previous: Option<Vec<Option<Player>>>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl Tracker {
+    pub fn new() -> Tracker {
+        Tracker {
+            previous: None,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn subscribe<F>(&mut self, subscriber: F)
+    where
+        F: FnMut(&Event) + Send + 'static,
+    {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// Record a new snapshot, notify subscribers and return the events it produced
+    ///
+    /// The very first snapshot only establishes a baseline: reporting every player already in
+    /// the game as having just joined would be noise.
+    pub fn update(&mut self, players: Vec<Option<Player>>) -> Vec<Event> {
+        let events = match &self.previous {
+            Some(previous) => diff(previous, &players),
+            None => Vec::new(),
+        };
+
+        for subscriber in self.subscribers.iter_mut() {
+            for event in &events {
+                subscriber(event);
+            }
+        }
+
+        self.previous = Some(players);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec3;
+
+    /// A name for the case, the slots before and after, and the events expected in between
+    type Case = (
+        &'static str,
+        Vec<Option<Player>>,
+        Vec<Option<Player>>,
+        Vec<Event>,
+    );
+
+    fn player(name: &str, health: i32) -> Option<Player> {
+        Some(Player {
+            name_raw: name.as_bytes().to_vec(),
+            name: String::from(name),
+            health,
+            ..Player::default()
+        })
+    }
+
+    fn at(player: Option<Player>, x: f32) -> Option<Player> {
+        player.map(|player| Player {
+            position: Vec3::new(x, 0.0, 0.0),
+            ..player
+        })
+    }
+
+    #[test]
+    fn diff_reports_what_changed() {
+        let cases: Vec<Case> = vec![
+            (
+                "nothing",
+                vec![player("a", 100)],
+                vec![player("a", 100)],
+                vec![],
+            ),
+            (
+                "join",
+                vec![None],
+                vec![player("a", 100)],
+                vec![Event::Joined {
+                    slot: 0,
+                    name: String::from("a"),
+                }],
+            ),
+            (
+                "join past the end",
+                vec![player("a", 100)],
+                vec![player("a", 100), player("b", 100)],
+                vec![Event::Joined {
+                    slot: 1,
+                    name: String::from("b"),
+                }],
+            ),
+            (
+                "leave",
+                vec![player("a", 100)],
+                vec![None],
+                vec![Event::Left {
+                    slot: 0,
+                    name: String::from("a"),
+                }],
+            ),
+            (
+                "damage",
+                vec![player("a", 100)],
+                vec![player("a", 60)],
+                vec![Event::Damaged {
+                    slot: 0,
+                    name: String::from("a"),
+                    amount: 40,
+                }],
+            ),
+            (
+                "death",
+                vec![player("a", 30)],
+                vec![player("a", -10)],
+                vec![
+                    Event::Damaged {
+                        slot: 0,
+                        name: String::from("a"),
+                        amount: 30,
+                    },
+                    Event::Died {
+                        slot: 0,
+                        name: String::from("a"),
+                    },
+                ],
+            ),
+            (
+                "respawn",
+                vec![at(player("a", 0), 0.0)],
+                vec![at(player("a", 100), 500.0)],
+                vec![Event::Respawned {
+                    slot: 0,
+                    name: String::from("a"),
+                }],
+            ),
+            (
+                "teleport",
+                vec![at(player("a", 100), 0.0)],
+                vec![at(player("a", 100), 100.0)],
+                vec![Event::Teleported {
+                    slot: 0,
+                    name: String::from("a"),
+                    distance: 100.0,
+                }],
+            ),
+            (
+                "walk",
+                vec![at(player("a", 100), 0.0)],
+                vec![at(player("a", 100), 10.0)],
+                vec![],
+            ),
+            (
+                "rename",
+                vec![player("a", 80)],
+                vec![player("b", 80)],
+                vec![Event::Renamed {
+                    slot: 0,
+                    from: String::from("a"),
+                    to: String::from("b"),
+                }],
+            ),
+            (
+                "slot reused",
+                vec![player("a", 20)],
+                vec![player("b", 100)],
+                vec![
+                    Event::Left {
+                        slot: 0,
+                        name: String::from("a"),
+                    },
+                    Event::Joined {
+                        slot: 0,
+                        name: String::from("b"),
+                    },
+                ],
+            ),
+        ];
+
+        for (name, before, after, expected) in cases {
+            assert_eq!(diff(&before, &after), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn tracker_starts_from_a_baseline() {
+        let mut tracker = Tracker::new();
+        assert!(tracker.update(vec![player("a", 100)]).is_empty());
+
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = std::sync::Arc::clone(&seen);
+        tracker.subscribe(move |event| {
+            sink.lock()
+                .unwrap()
+                .push((event.slot(), String::from(event.name())))
+        });
+
+        tracker.update(vec![player("a", 100), player("b", 100)]);
+        assert_eq!(*seen.lock().unwrap(), vec![(1, String::from("b"))]);
+    }
+}
@@ -0,0 +1,68 @@
+//! What the trainer can do, and the one place each feature is registered
+//!
+//! A feature is run by the scheduler: it's attached to the game, ticked with a shared snapshot of
+//! the game state for as long as the game runs, and detached when it exits. Adding a feature means
+//! implementing `Feature` and adding it to `registry`.
+
+use std::time::Duration;
+
+use crate::aimbot;
+use crate::config::Config;
+use crate::dashboard::Board;
+use crate::godmode;
+use crate::input;
+use crate::proc::ProcessMemory;
+use crate::scheduler::WorldSnapshot;
+use crate::table;
+use crate::triggerbot;
+
+/// A setting a feature takes from the configuration file
+pub struct Setting {
+    pub section: &'static str,
+    pub key: &'static str,
+    pub description: &'static str,
+}
+
+pub trait Feature {
+    /// The name used for the feature in `[features]` and on the command line
+    fn name(&self) -> &'static str;
+
+    /// The configuration file settings the feature reads
+    fn schema(&self) -> &'static [Setting];
+
+    /// Take on a new configuration
+    fn configure(&mut self, config: &Config);
+
+    /// Get ready to run against a newly attached game, forgetting about any previous one
+    fn on_attach(&mut self, _memory: &mut dyn ProcessMemory) {}
+
+    /// Do one tick's work, returning how long to wait before the next
+    ///
+    /// Errors are taken to be passing, and the tick is tried again shortly; see `scheduler`.
+    fn on_tick(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        memory: &mut dyn ProcessMemory,
+    ) -> Result<Duration, String>;
+
+    /// Clean up before letting go of the game, which may already have exited
+    fn on_detach(&mut self, _memory: &mut dyn ProcessMemory) {}
+}
+
+/// Every feature, in the order they're ticked
+///
+/// The board connects them to the dashboard.
+pub fn registry(config: &Config, input: &input::Shared, board: &Board) -> Vec<Box<dyn Feature>> {
+    vec![
+        Box::new(aimbot::Aimbot::new(
+            aimbot::Settings::new(config, Box::new(input.clone())),
+            board.clone(),
+        )),
+        Box::new(triggerbot::Triggerbot::new(triggerbot::Settings::new(
+            config,
+            Box::new(input.clone()),
+        ))),
+        Box::new(godmode::Godmode::new(config)),
+        Box::new(table::Table::new(config, board.clone())),
+    ]
+}
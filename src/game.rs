@@ -0,0 +1,95 @@
+//! Game-wide state that isn't tied to a single entity
+
+use crate::entities::Player;
+use crate::proc::{self, ProcessMemory, Read};
+
+pub const GAME_MODE_ADDR: proc::Address = 0x50f49c;
+
+// Team numbers as stored in the player entity. Anything above the two playing teams is one of the
+// spectator teams.
+pub const TEAM_CLA: i32 = 0;
+pub const TEAM_RVSF: i32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GameMode(pub i32);
+
+impl GameMode {
+    pub fn read<M: ProcessMemory>(memory: M) -> Result<GameMode, String> {
+        Ok(GameMode(i32::read(memory, GAME_MODE_ADDR)?))
+    }
+
+    /// Whether players are split into teams, as opposed to everyone fighting everyone
+    pub fn is_team_mode(self) -> bool {
+        match self.0 {
+            // team deathmatch, team survivor, CTF, bot team deathmatch, team one shot one kill,
+            // hunt the flag, team keep the flag, team pistol frenzy, team last swiss standing,
+            // bot team survivor, bot team one shot one kill
+            0 | 4 | 5 | 7 | 11 | 13 | 14 | 16 | 17 | 20 | 21 => true,
+            _ => false,
+        }
+    }
+
+    /// Whether `other` is someone `me` should be shooting at
+    pub fn hostile(self, me: &Player, other: &Player) -> bool {
+        if !is_playing(other) {
+            return false;
+        }
+        !self.is_team_mode() || other.team != me.team
+    }
+}
+
+/// Whether a player is on one of the playing teams, rather than spectating
+pub fn is_playing(player: &Player) -> bool {
+    player.team == TEAM_CLA || player.team == TEAM_RVSF
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEAM_MODES: [i32; 11] = [0, 4, 5, 7, 11, 13, 14, 16, 17, 20, 21];
+
+    fn player(team: i32) -> Player {
+        Player {
+            team,
+            ..Player::default()
+        }
+    }
+
+    #[test]
+    fn team_modes() {
+        for mode in -1..=22 {
+            let expected = TEAM_MODES.contains(&mode);
+            assert_eq!(GameMode(mode).is_team_mode(), expected, "mode {}", mode);
+        }
+        // Numbers the game doesn't know of are treated as free for all.
+        for &mode in &[-5, 23, 100, i32::MAX] {
+            assert!(!GameMode(mode).is_team_mode(), "mode {}", mode);
+        }
+    }
+
+    #[test]
+    fn only_the_other_team_is_hostile_in_team_modes() {
+        let (cla, rvsf, spectator) = (player(TEAM_CLA), player(TEAM_RVSF), player(4));
+        for &mode in &TEAM_MODES {
+            let mode = GameMode(mode);
+            assert!(mode.hostile(&cla, &rvsf), "{:?}", mode);
+            assert!(mode.hostile(&rvsf, &cla), "{:?}", mode);
+            assert!(!mode.hostile(&cla, &cla), "{:?}", mode);
+            assert!(!mode.hostile(&cla, &spectator), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn everyone_playing_is_hostile_otherwise() {
+        let (cla, rvsf, spectator) = (player(TEAM_CLA), player(TEAM_RVSF), player(4));
+        // Deathmatch, one shot one kill, and a mode number the game doesn't know of
+        for &mode in &[2, 10, 99] {
+            let mode = GameMode(mode);
+            assert!(mode.hostile(&cla, &cla), "{:?}", mode);
+            assert!(mode.hostile(&cla, &rvsf), "{:?}", mode);
+            // Spectators are never a target.
+            assert!(!mode.hostile(&cla, &spectator), "{:?}", mode);
+        }
+    }
+}
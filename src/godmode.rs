@@ -0,0 +1,91 @@
+//! Keeping the godmode patch in line with the configuration
+
+use std::time::Duration;
+
+use crate::code;
+use crate::config::Config;
+use crate::feature::{Feature, Setting};
+use crate::proc::ProcessMemory;
+use crate::scheduler::WorldSnapshot;
+
+/// How often to check whether the patch needs switching
+const TICK: Duration = Duration::from_millis(250);
+
+const SCHEMA: &[Setting] = &[];
+
+pub struct Godmode {
+    wanted: bool,
+    enabled: bool,
+    /// The injected hook, once godmode has been switched on for the first time
+    injection: Option<code::Injection>,
+}
+
+impl Godmode {
+    pub fn new(config: &Config) -> Godmode {
+        Godmode {
+            wanted: config.features.godmode,
+            enabled: false,
+            injection: None,
+        }
+    }
+}
+
+impl Feature for Godmode {
+    fn name(&self) -> &'static str {
+        "godmode"
+    }
+
+    fn schema(&self) -> &'static [Setting] {
+        SCHEMA
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.wanted = config.features.godmode;
+    }
+
+    fn on_attach(&mut self, _memory: &mut dyn ProcessMemory) {
+        // Code injected into a previous game is gone along with it.
+        self.enabled = false;
+        self.injection = None;
+    }
+
+    fn on_tick(
+        &mut self,
+        _snapshot: &WorldSnapshot,
+        memory: &mut dyn ProcessMemory,
+    ) -> Result<Duration, String> {
+        if self.wanted == self.enabled {
+            return Ok(TICK);
+        }
+
+        if self.wanted && self.injection.is_none() {
+            let injection = code::godmode()
+                .inject(&mut *memory)
+                .map_err(|err| format!("failed to inject godmode hook: {}", err))?;
+            self.injection = Some(injection);
+        }
+
+        if let Some(injection) = &self.injection {
+            if self.wanted {
+                injection
+                    .enable(memory)
+                    .map_err(|err| format!("failed to enable godmode hook: {}", err))?;
+            } else {
+                injection
+                    .disable(memory)
+                    .map_err(|err| format!("failed to disable godmode hook: {}", err))?;
+            }
+        }
+        self.enabled = self.wanted;
+
+        Ok(TICK)
+    }
+
+    fn on_detach(&mut self, memory: &mut dyn ProcessMemory) {
+        // Leave the game as we found it. It may have exited already, in which case there's
+        // nothing to undo.
+        if let (true, Some(injection)) = (self.enabled, &self.injection) {
+            let _ = injection.disable(memory);
+        }
+    }
+}
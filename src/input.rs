@@ -0,0 +1,311 @@
+//! Keyboard and mouse input, for deciding when features are active
+//!
+//! Features don't query the OS directly. They poll an `Activation`, which turns raw key states
+//! from an `InputSource` into on/off according to a hold or toggle mode. Swapping the source is
+//! all it takes to drive a feature from somewhere else, such as a scripted sequence of key states.
+//!
+//! Only Windows key states are read. Reading evdev on Linux would need a Linux build, and there
+//! isn't one: the trainer gets at the game through the Win32 API, and under Wine it's still a
+//! Windows program that has no business opening `/dev/input`.
+
+use std::sync::{Arc, Mutex};
+
+use crate::winapi;
+
+/// A key or mouse button, independent of how the platform numbers them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A letter or digit key, always uppercase
+    Char(char),
+    /// A function key, from F1 to F12
+    Function(u8),
+    CapsLock,
+    Shift,
+    Control,
+    Alt,
+    Space,
+    Tab,
+    MouseLeft,
+    MouseRight,
+    MouseMiddle,
+    MouseBack,
+    MouseForward,
+}
+
+impl Key {
+    /// Parse a key name such as `capslock`, `mouse2`, `f5` or `e`
+    pub fn from_name(name: &str) -> Result<Key, String> {
+        let lower = name.to_ascii_lowercase();
+        let key = match lower.as_str() {
+            "capslock" | "caps" => Key::CapsLock,
+            "shift" => Key::Shift,
+            "ctrl" | "control" => Key::Control,
+            "alt" => Key::Alt,
+            "space" => Key::Space,
+            "tab" => Key::Tab,
+            "mouse1" | "lmb" => Key::MouseLeft,
+            "mouse2" | "rmb" => Key::MouseRight,
+            "mouse3" | "mmb" => Key::MouseMiddle,
+            "mouse4" => Key::MouseBack,
+            "mouse5" => Key::MouseForward,
+            _ => {
+                let mut chars = lower.chars();
+                match (chars.next(), chars.as_str()) {
+                    (Some(c), "") if c.is_ascii_alphanumeric() => Key::Char(c.to_ascii_uppercase()),
+                    (Some('f'), n) => match n.parse() {
+                        Ok(n @ 1..=12) => Key::Function(n),
+                        _ => return Err(format!("unknown key: {}", name)),
+                    },
+                    _ => return Err(format!("unknown key: {}", name)),
+                }
+            }
+        };
+        Ok(key)
+    }
+
+    /// The Windows virtual-key code for this key
+    pub fn virtual_key(self) -> winapi::c_int {
+        match self {
+            // Virtual-key codes for letters and digits are their (uppercase) ASCII codes.
+            Key::Char(c) => c as winapi::c_int,
+            Key::Function(n) => winapi::VK_F1 + n as winapi::c_int - 1,
+            Key::CapsLock => winapi::VK_CAPITAL,
+            Key::Shift => winapi::VK_SHIFT,
+            Key::Control => winapi::VK_CONTROL,
+            Key::Alt => winapi::VK_MENU,
+            Key::Space => winapi::VK_SPACE,
+            Key::Tab => winapi::VK_TAB,
+            Key::MouseLeft => winapi::VK_LBUTTON,
+            Key::MouseRight => winapi::VK_RBUTTON,
+            Key::MouseMiddle => winapi::VK_MBUTTON,
+            Key::MouseBack => winapi::VK_XBUTTON1,
+            Key::MouseForward => winapi::VK_XBUTTON2,
+        }
+    }
+}
+
+/// Somewhere key states can be read from
+pub trait InputSource: Send {
+    /// Whether the key is held down right now
+    fn is_down(&mut self, key: Key) -> bool;
+
+    /// Whether the key is held down, or was pressed at some point since the last call
+    ///
+    /// Reporting presses that were released again before we got to look keeps quick taps from
+    /// being lost between polls, which matters for toggles. A hold shouldn't count them, though:
+    /// it would stay active for a poll after the key was let go.
+    fn was_pressed(&mut self, key: Key) -> bool {
+        self.is_down(key)
+    }
+
+    /// Whether the OS has the key's lock turned on, for keys that have one, such as Caps Lock
+    ///
+    /// Toggling such a key then follows the OS, keeping it in step with the keyboard's light.
+    fn is_locked(&mut self, _key: Key) -> Option<bool> {
+        None
+    }
+}
+
+/// Reads key states through the Windows API, no matter which window has focus
+pub struct AsyncKeyState;
+
+impl InputSource for AsyncKeyState {
+    fn is_down(&mut self, key: Key) -> bool {
+        // The high bit means the key is down right now.
+        let state = unsafe { winapi::GetAsyncKeyState(key.virtual_key()) } as u16;
+        state & 0x8000 != 0
+    }
+
+    fn was_pressed(&mut self, key: Key) -> bool {
+        // The low bit means it was pressed since the previous call.
+        let state = unsafe { winapi::GetAsyncKeyState(key.virtual_key()) } as u16;
+        state & 0x8001 != 0
+    }
+
+    fn is_locked(&mut self, key: Key) -> Option<bool> {
+        if key != Key::CapsLock {
+            return None;
+        }
+        // The low bit is the toggle state, which the OS keeps for every thread alike only for
+        // lock keys.
+        Some(unsafe { winapi::GetKeyState(key.virtual_key()) } & 1 == 1)
+    }
+}
+
+/// One input source handed to several features
+#[derive(Clone)]
+pub struct Shared(Arc<Mutex<Box<dyn InputSource>>>);
+
+impl Shared {
+    pub fn new(source: Box<dyn InputSource>) -> Shared {
+        Shared(Arc::new(Mutex::new(source)))
+    }
+}
+
+impl InputSource for Shared {
+    fn is_down(&mut self, key: Key) -> bool {
+        self.0.lock().expect("input source poisoned").is_down(key)
+    }
+
+    fn was_pressed(&mut self, key: Key) -> bool {
+        self.0
+            .lock()
+            .expect("input source poisoned")
+            .was_pressed(key)
+    }
+
+    fn is_locked(&mut self, key: Key) -> Option<bool> {
+        self.0.lock().expect("input source poisoned").is_locked(key)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Active while the key is held down
+    Hold,
+    /// Each press switches between active and inactive, or, for a lock key, active while it's
+    /// locked
+    Toggle,
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Result<Mode, String> {
+        match name {
+            "hold" => Ok(Mode::Hold),
+            "toggle" => Ok(Mode::Toggle),
+            _ => Err(format!("unknown activation mode: {}", name)),
+        }
+    }
+}
+
+/// Decides whether a feature is active from the state of a key
+pub struct Activation {
+    pub key: Key,
+    pub mode: Mode,
+    active: bool,
+    was_down: bool,
+}
+
+impl Activation {
+    pub fn new(key: Key, mode: Mode) -> Activation {
+        Activation {
+            key,
+            mode,
+            active: false,
+            was_down: false,
+        }
+    }
+
+    /// Sample the key and return whether the feature should be active
+    pub fn poll(&mut self, input: &mut dyn InputSource) -> bool {
+        let down = match self.mode {
+            Mode::Hold => input.is_down(self.key),
+            Mode::Toggle => input.was_pressed(self.key),
+        };
+        let pressed = down && !self.was_down;
+        self.was_down = down;
+
+        match self.mode {
+            Mode::Hold => self.active = down,
+            Mode::Toggle => match input.is_locked(self.key) {
+                Some(locked) => self.active = locked,
+                None if pressed => self.active = !self.active,
+                None => {}
+            },
+        }
+
+        self.active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays back one key state per poll, along with taps in between and a lock state if given
+    struct Script {
+        down: Vec<bool>,
+        taps: Vec<bool>,
+        locked: Option<Vec<bool>>,
+        polls: usize,
+    }
+
+    impl Script {
+        fn new(down: &[bool]) -> Script {
+            Script {
+                down: down.to_vec(),
+                taps: vec![false; down.len()],
+                locked: None,
+                polls: 0,
+            }
+        }
+    }
+
+    impl InputSource for Script {
+        fn is_down(&mut self, _key: Key) -> bool {
+            self.polls += 1;
+            self.down[self.polls - 1]
+        }
+
+        fn was_pressed(&mut self, key: Key) -> bool {
+            self.is_down(key) || self.taps[self.polls - 1]
+        }
+
+        fn is_locked(&mut self, _key: Key) -> Option<bool> {
+            self.locked.as_ref().map(|locked| locked[self.polls - 1])
+        }
+    }
+
+    fn run(mode: Mode, mut script: Script) -> Vec<bool> {
+        let mut activation = Activation::new(Key::Char('E'), mode);
+        (0..script.down.len())
+            .map(|_| activation.poll(&mut script))
+            .collect()
+    }
+
+    const T: bool = true;
+    const F: bool = false;
+
+    #[test]
+    fn hold_follows_the_key() {
+        let down = [F, T, T, F, T, F];
+        assert_eq!(run(Mode::Hold, Script::new(&down)), down);
+    }
+
+    #[test]
+    fn toggle_flips_on_each_press() {
+        assert_eq!(
+            run(Mode::Toggle, Script::new(&[F, T, T, F, T, F, F, T])),
+            [F, T, T, T, F, F, F, T]
+        );
+    }
+
+    #[test]
+    fn only_toggles_see_taps_between_polls() {
+        let script = || Script {
+            taps: vec![F, T, F, F, F, T],
+            ..Script::new(&[F, F, F, T, F, F])
+        };
+        assert_eq!(run(Mode::Hold, script()), [F, F, F, T, F, F]);
+        assert_eq!(run(Mode::Toggle, script()), [F, T, T, F, F, T]);
+    }
+
+    #[test]
+    fn toggle_follows_the_lock_when_there_is_one() {
+        let script = Script {
+            locked: Some(vec![T, T, F, F, T]),
+            ..Script::new(&[F, T, F, T, F])
+        };
+        assert_eq!(run(Mode::Toggle, script), [T, T, F, F, T]);
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(Key::from_name("CapsLock"), Ok(Key::CapsLock));
+        assert_eq!(Key::from_name("mouse2"), Ok(Key::MouseRight));
+        assert_eq!(Key::from_name("f12"), Ok(Key::Function(12)));
+        assert_eq!(Key::from_name("e"), Ok(Key::Char('E')));
+        assert!(Key::from_name("f13").is_err());
+        assert!(Key::from_name("ee").is_err());
+    }
+}
@@ -1,70 +1,419 @@
 mod aimbot;
+mod cli;
 mod code;
+mod config;
+mod dashboard;
 mod entities;
+mod events;
+mod feature;
+mod game;
+mod godmode;
+mod input;
+mod map;
+mod math;
+mod output;
 mod proc;
+mod scheduler;
+mod selftest;
+mod table;
+mod triggerbot;
+mod weapons;
 mod winapi;
 mod winapi_error;
+mod world;
 
-use entities::Player;
-use proc::Read;
+use std::time::Duration;
+
+use cli::Command;
+use config::Config;
+use map::Map;
+use proc::pattern::{self, Pattern};
+use proc::pe::{Image, Section};
+use scheduler::Scheduler;
 
 fn main() {
-    loop {
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let result = match command {
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Command::Run(options) => {
+            run(&options);
+            Ok(())
+        }
+        Command::Scan {
+            options,
+            pattern,
+            module,
+        } => scan(&options, &pattern, module.as_deref()),
+        Command::Dump { options, what } => dump(&options, what.as_deref()),
+        Command::Patch {
+            options,
+            name,
+            enable,
+        } => patch(&options, &name, enable),
+        Command::SelfTest(options) => selftest(&options),
+        Command::Features => {
+            list_features();
+            Ok(())
+        }
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// How long to wait before looking for the game again
+const WAIT_FOR_GAME: Duration = Duration::from_millis(1000);
+
+/// The PID to attach to, if the game is running
+fn find_game(options: &cli::Options) -> Option<proc::Pid> {
+    options.pid.or_else(|| proc::find(&options.process))
+}
+
+/// Attach to the game for a one-off command, failing if it isn't running
+fn attach(options: &cli::Options) -> Result<(proc::Pid, proc::Handle), String> {
+    let pid = find_game(options).ok_or_else(|| format!("{} is not running", options.process))?;
+    Ok((pid, proc::open(pid)?))
+}
+
+fn run(options: &cli::Options) {
+    let mut watcher = config::Watcher::new(options.config_path());
+    let mut config = Config::default();
+    options.apply(&mut config);
+    reload(&mut watcher, options, &mut config);
+
+    // Every feature always runs so that it can be switched on by a configuration change; while
+    // switched off, features only watch their hotkeys.
+    let input = input::Shared::new(Box::new(input::AsyncKeyState));
+    let board = dashboard::Board::default();
+    let mut scheduler = Scheduler::new(feature::registry(&config, &input, &board));
+    if let Err(err) = scheduler::catch_interrupts() {
+        eprintln!("Ctrl+C will quit without detaching: {}", err);
+    }
+
+    while !scheduler::interrupted() {
         let pid = loop {
-            match proc::find("ac_client.exe") {
+            match find_game(options) {
                 Some(pid) => break pid,
+                None if scheduler::interrupted() => return,
                 None => {
                     eprintln!("Waiting for game to launch...");
-                    std::thread::sleep(std::time::Duration::from_millis(1000));
+                    std::thread::sleep(WAIT_FOR_GAME);
                     continue;
                 }
             }
         };
 
-        aimbot::spawn_thread(pid);
+        let handle = match proc::open(pid) {
+            Ok(handle) => handle,
+            Err(err) => {
+                eprintln!("Failed to attach to the game: {}", err);
+                // A specific PID that can't be opened has most likely exited.
+                if options.pid.is_some() {
+                    return;
+                }
+                std::thread::sleep(WAIT_FOR_GAME);
+                continue;
+            }
+        };
+
+        let result = scheduler.run(handle, &mut || {
+            let reloaded = reload(&mut watcher, options, &mut config);
+            // Features switched from the dashboard stay switched when the file changes.
+            let toggled = board.apply(&mut config);
+            if reloaded || toggled {
+                Some(config.clone())
+            } else {
+                None
+            }
+        });
 
-        let handle = proc::open(pid).expect("failed to open process");
+        // Closing only fails for a handle that's already unusable, which leaves nothing to clean up.
+        let _ = proc::close(handle);
 
-        code::godmode()
-            .inject(handle)
-            .expect("failed to inject godmode hook")
-            .enable(handle)
-            .expect("failed to enable godmode hook");
+        match result {
+            Ok(()) if scheduler::interrupted() => {}
+            Ok(()) => {
+                eprintln!("Game closed.");
 
-        while proc::still_active(handle).expect("failed to check process exit code") {
-            run_once(handle);
+                // A specific PID won't come back once it's gone.
+                if options.pid.is_some() {
+                    return;
+                }
+            }
+            Err(err) => {
+                eprintln!("Detached from the game: {}", err);
+                std::thread::sleep(WAIT_FOR_GAME);
+            }
         }
+    }
+}
 
-        proc::close(handle).expect("failed to close process");
+/// Pick up changes to the configuration file, returning whether there were any
+///
+/// A configuration that fails to parse is reported and otherwise ignored, leaving the previous one
+/// in place until the file is fixed.
+fn reload(watcher: &mut config::Watcher, options: &cli::Options, config: &mut Config) -> bool {
+    match watcher.poll() {
+        Some(Ok(mut new)) => {
+            options.apply(&mut new);
+            *config = new;
+            true
+        }
+        Some(Err(err)) => {
+            eprintln!("Ignoring configuration: {}", err);
+            false
+        }
+        None => false,
+    }
+}
+
+fn scan(options: &cli::Options, pattern: &str, module: Option<&str>) -> Result<(), String> {
+    let pattern = Pattern::parse(pattern)?;
+    let (pid, handle) = attach(options)?;
+    let module = proc::module(pid, module.unwrap_or(&options.process))?;
+    let image = Image::read_remote(handle, module.base)?;
+
+    let found = pattern::scan(handle, module.base, &image, &pattern)?;
+    for addr in &found {
+        println!("{:#010x}  {}+{:#x}", addr, module.name, addr - module.base);
+    }
+    eprintln!("{} matches", found.len());
 
-        eprintln!("Game closed.");
+    proc::close(handle)
+}
+
+/// Describe a module loaded in the game, or a PE image or map on disk
+fn dump(options: &cli::Options, what: Option<&str>) -> Result<(), String> {
+    match what {
+        Some(path) if path.ends_with(".cgz") => {
+            print_map(&Map::read_file(path)?);
+            Ok(())
+        }
+        Some(path) if std::path::Path::new(path).is_file() => {
+            print_image(&Image::read_file(path)?, None);
+            Ok(())
+        }
+        _ => {
+            let (pid, handle) = attach(options)?;
+            let module = proc::module(pid, what.unwrap_or(&options.process))?;
+            print_image(&Image::read_remote(handle, module.base)?, Some(&module));
+            proc::close(handle)
+        }
     }
 }
 
-fn run_once(handle: proc::Handle) {
-    print_header();
+fn patch(options: &cli::Options, name: &str, enable: bool) -> Result<(), String> {
+    let spec = code::by_name(name).ok_or_else(|| {
+        format!(
+            "unknown patch: {} (known: {})",
+            name,
+            code::PATCHES.join(", ")
+        )
+    })?;
+    let (_, handle) = attach(options)?;
+
+    match (spec.is_applied(handle)?, enable) {
+        (true, true) => eprintln!("{} is already enabled", name),
+        (false, false) => eprintln!("{} is already disabled", name),
+        (false, true) => {
+            spec.inject(handle)?.enable(handle)?;
+            eprintln!("Enabled {}", name);
+        }
+        (true, false) => {
+            spec.restore(handle)?;
+            eprintln!("Disabled {}", name);
+        }
+    }
+
+    proc::close(handle)
+}
 
-    let my_addr = proc::Address::read(handle, 0x50f4f4).expect("failed to read player pointer");
-    let me = Player::read(handle, my_addr).expect("failed to read player entity");
-    print_player(&me);
+fn selftest(options: &cli::Options) -> Result<(), String> {
+    let (_, handle) = attach(options)?;
+    let passed = selftest::run(handle);
+    proc::close(handle)?;
 
-    let players = entities::player_list(handle).expect("failed to read player list");
-    for player in players {
-        print_player(&player);
+    if passed {
+        Ok(())
+    } else {
+        Err(String::from("some checks failed"))
     }
+}
 
-    println!("");
-    std::thread::sleep(std::time::Duration::from_millis(1000));
+/// List every feature with the configuration file settings it reads
+fn list_features() {
+    let input = input::Shared::new(Box::new(input::AsyncKeyState));
+    let board = dashboard::Board::default();
+    for feature in feature::registry(&Config::default(), &input, &board) {
+        println!("{}", feature.name());
+        println!("    [features] {:24}Whether it's on", feature.name());
+        for setting in feature.schema() {
+            let name = format!("[{}] {}", setting.section, setting.key);
+            println!("    {:35}{}", name, setting.description);
+        }
+    }
 }
 
-fn print_header() {
-    println!("Name              Health  Armor  X           Y           Z");
-    println!("===================================================================");
+fn print_map(map: &Map) {
+    println!("Title:       {}", map.title);
+    println!("Version:     {}", map.version);
+    println!("Size:        {0}x{0}", map.world.size());
+    if let Some(water_level) = map.water_level {
+        println!("Water level: {}", water_level);
+    }
+    if let Some(revision) = map.revision {
+        println!("Revision:    {}", revision);
+    }
+    println!("Entities:    {}", map.entities.len());
+    println!("Spawns:      {}", map.spawns().count());
+
+    let spawns: Vec<&map::Entity> = map.spawns().collect();
+    if !spawns.is_empty() {
+        println!();
+        println!("Spawn for  X       Y       Z       Yaw");
+        println!("===================================================================");
+        for spawn in spawns {
+            let team = match spawn.spawn_team() {
+                Some(game::TEAM_CLA) => "CLA",
+                Some(_) => "RVSF",
+                None => "anyone",
+            };
+            println!(
+                "{:9}  {:<6}  {:<6}  {:<6}  {}",
+                team, spawn.x, spawn.y, spawn.z, spawn.attr1
+            );
+        }
+    }
+
+    let items = map.items();
+    if !items.is_empty() {
+        println!();
+        table::print_item_header();
+        for item in &items {
+            table::print_item(item);
+        }
+    }
 }
 
-fn print_player(player: &Player) {
+fn print_image(image: &Image, module: Option<&proc::Module>) {
+    if let Some(module) = module {
+        println!("Module:      {}", module.name);
+        println!("Base:        {:#010x}", module.base);
+        println!("Size:        {:#x}", module.size);
+    }
+    println!("Machine:     {:#06x}", image.machine);
     println!(
-        "{:16}  {:<4}    {:<4}   {:<10}  {:<10}  {:<10}",
-        player.name, player.health, player.armor, player.x, player.y, player.z,
+        "Format:      {}",
+        if image.pe32_plus { "PE32+" } else { "PE32" }
     );
+    println!("Timestamp:   {:#010x}", image.timestamp);
+    println!("Flags:       {:#06x}", image.characteristics);
+    println!("Image base:  {:#x}", image.image_base);
+    println!("Image size:  {:#x}", image.size_of_image);
+    println!("Headers:     {:#x}", image.size_of_headers);
+    println!("Entry point: {:#x}", image.entry_point);
+
+    println!();
+    println!("Section   RVA         Size        Flags");
+    println!("===================================================================");
+    for section in &image.sections {
+        let flag = |bit: u32, c: char| {
+            if section.characteristics & bit != 0 {
+                c
+            } else {
+                '-'
+            }
+        };
+        println!(
+            "{:8}  {:#010x}  {:#010x}  {}{}{}",
+            section.name,
+            section.virtual_address,
+            section.virtual_size,
+            flag(Section::READ, 'r'),
+            flag(Section::WRITE, 'w'),
+            flag(Section::EXECUTE, 'x'),
+        );
+    }
+
+    if !image.imports.is_empty() {
+        println!();
+        println!("Import                                    Ordinal  IAT");
+        println!("===================================================================");
+        for import in &image.imports {
+            println!("{} ({} functions)", import.dll, import.functions.len());
+            for function in &import.functions {
+                println!(
+                    "  {:38}  {:<7}  {:#010x}",
+                    function.name.as_deref().unwrap_or("(by ordinal)"),
+                    function
+                        .ordinal
+                        .map(|ordinal| ordinal.to_string())
+                        .unwrap_or_default(),
+                    function.iat_rva,
+                );
+            }
+        }
+    }
+
+    if !image.exports.is_empty() {
+        println!();
+        println!("Export                                    Ordinal  RVA");
+        println!("===================================================================");
+        for export in &image.exports {
+            match &export.forwarder {
+                Some(forwarder) => println!(
+                    "{:40}  {:<7}  -> {}",
+                    export.name.as_deref().unwrap_or("(unnamed)"),
+                    export.ordinal,
+                    forwarder,
+                ),
+                None => println!(
+                    "{:40}  {:<7}  {:#010x}",
+                    export.name.as_deref().unwrap_or("(unnamed)"),
+                    export.ordinal,
+                    export.rva,
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_keeps_the_old_config_when_the_new_one_is_broken() {
+        let path =
+            std::env::temp_dir().join(format!("ac-trainer-{}-reload.ini", std::process::id()));
+        std::fs::write(&path, "[radar]\nzoom = 2\n").unwrap();
+        let options = cli::Options {
+            config: Some(path.clone()),
+            ..cli::Options::default()
+        };
+        let mut watcher = config::Watcher::new(options.config_path());
+        let mut config = Config::default();
+        assert!(reload(&mut watcher, &options, &mut config));
+        assert_eq!(config.radar.zoom, 2.0);
+
+        // A fresh watcher sees the broken file as a change whatever its modification time.
+        std::fs::write(&path, "[radar]\nzoom = 0\n").unwrap();
+        let mut watcher = config::Watcher::new(options.config_path());
+        let reloaded = reload(&mut watcher, &options, &mut config);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!reloaded);
+        assert_eq!(config.radar.zoom, 2.0);
+    }
 }
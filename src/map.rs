@@ -0,0 +1,461 @@
+//! Parser for AssaultCube map files (`.cgz`)
+//!
+//! A map file is a gzip-compressed stream holding a fixed header, the list of map entities and
+//! then the square-grid geometry, one run-length encoded cell at a time.
+
+pub mod inflate;
+
+use std::convert::TryFrom;
+
+use crate::entities::{self, Item, ItemKind};
+use crate::game;
+use crate::math::Vec3;
+use crate::world::{Cell, CellKind, World};
+
+// Offsets into the map header. Older versions have shorter headers, so everything past the texture
+// lists is only read when the header says it's big enough.
+const HEADER_VERSION_OFFSET: usize = 4;
+const HEADER_SIZE_OFFSET: usize = 8;
+const HEADER_SFACTOR_OFFSET: usize = 12;
+const HEADER_NUMENTS_OFFSET: usize = 16;
+const HEADER_TITLE_OFFSET: usize = 20;
+const HEADER_TITLE_SIZE: usize = 128;
+/// The smallest header any version has: everything up to and including the title
+const HEADER_MIN_SIZE: usize = HEADER_TITLE_OFFSET + HEADER_TITLE_SIZE;
+const HEADER_WATERLEVEL_OFFSET: usize = 916;
+const HEADER_REVISION_OFFSET: usize = 924;
+
+/// Entity type of a player spawn point
+const ENTITY_PLAYERSTART: u8 = 2;
+
+// Cell type markers in the geometry stream besides the cell types themselves.
+const CELL_RUN: u8 = 255;
+const CELL_RELIT: u8 = 254;
+
+pub struct Map {
+    pub version: i32,
+    pub title: String,
+    pub water_level: Option<i32>,
+    pub revision: Option<i32>,
+    pub entities: Vec<Entity>,
+    pub world: World,
+}
+
+/// An entity as stored in the map file
+///
+/// What the attributes mean depends on the kind of entity. Only the first two are kept, since the
+/// others don't matter for anything the trainer looks at.
+#[derive(Clone, Debug)]
+pub struct Entity {
+    pub kind: u8,
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+    /// For spawn points, the yaw players spawn facing
+    pub attr1: i16,
+    /// For spawn points, the team that spawns there; see `Entity::spawn_team`
+    pub attr2: u8,
+}
+
+impl Entity {
+    pub fn item(&self) -> Option<ItemKind> {
+        ItemKind::from_entity_type(self.kind)
+    }
+
+    pub fn is_spawn(&self) -> bool {
+        self.kind == ENTITY_PLAYERSTART
+    }
+
+    /// The team a spawn point is for, or `None` if anyone can spawn there
+    pub fn spawn_team(&self) -> Option<i32> {
+        match self.attr2 as i32 {
+            team @ (game::TEAM_CLA | game::TEAM_RVSF) => Some(team),
+            _ => None,
+        }
+    }
+}
+
+impl Map {
+    pub fn read_file<P: AsRef<std::path::Path>>(path: P) -> Result<Map, String> {
+        let data = std::fs::read(path).map_err(|err| format!("failed to read map: {}", err))?;
+        Map::parse(&data)
+    }
+
+    /// Parse a gzip-compressed map, as found on disk
+    pub fn parse(compressed: &[u8]) -> Result<Map, String> {
+        Map::parse_uncompressed(&inflate::gunzip(compressed)?)
+    }
+
+    pub fn parse_uncompressed(data: &[u8]) -> Result<Map, String> {
+        let mut input = Input { data, pos: 0 };
+
+        let magic = input.bytes(4)?;
+        if magic != b"ACMP" && magic != b"CUBE" {
+            return Err(String::from("not an AssaultCube map"));
+        }
+
+        let version = input.i32_at(HEADER_VERSION_OFFSET)?;
+        let header_size = input.i32_at(HEADER_SIZE_OFFSET)?;
+        let header_size = match usize::try_from(header_size) {
+            Ok(size) if (HEADER_MIN_SIZE..=data.len()).contains(&size) => size,
+            _ => return Err(format!("implausible header size: {}", header_size)),
+        };
+        let sfactor = input.i32_at(HEADER_SFACTOR_OFFSET)?;
+        let entity_count = input.i32_at(HEADER_NUMENTS_OFFSET)?;
+
+        if !(0..=10).contains(&sfactor) {
+            return Err(format!("implausible map size factor: {}", sfactor));
+        }
+        if !(0..=0xffff).contains(&entity_count) {
+            return Err(format!("implausible entity count: {}", entity_count));
+        }
+
+        let raw_title = input.bytes_at(HEADER_TITLE_OFFSET, HEADER_TITLE_SIZE)?;
+        let raw_title = raw_title.split(|&c| c == 0).next().unwrap_or_default();
+        let title = entities::decode_name(raw_title);
+
+        let optional = |input: &Input, offset: usize| -> Result<Option<i32>, String> {
+            if header_size >= offset + 4 {
+                input.i32_at(offset).map(Some)
+            } else {
+                Ok(None)
+            }
+        };
+        let water_level = optional(&input, HEADER_WATERLEVEL_OFFSET)?;
+        let revision = optional(&input, HEADER_REVISION_OFFSET)?;
+
+        input.pos = header_size;
+
+        // Version 10 added three more attributes to each entity, which we don't use.
+        let entity_size = if version >= 10 { 16 } else { 12 };
+        let mut entities = Vec::with_capacity(entity_count as usize);
+        for _ in 0..entity_count {
+            let raw = input.bytes(entity_size)?;
+            let short = |offset: usize| i16::from_le_bytes([raw[offset], raw[offset + 1]]);
+            entities.push(Entity {
+                x: short(0),
+                y: short(2),
+                z: short(4),
+                attr1: short(6),
+                kind: raw[8],
+                attr2: raw[9],
+            });
+        }
+
+        let world = parse_world(&mut input, version, sfactor as u32)?;
+
+        Ok(Map {
+            version,
+            title,
+            water_level,
+            revision,
+            entities,
+            world,
+        })
+    }
+
+    /// The pickups and flags placed on the map, as they are when the map starts
+    pub fn items(&self) -> Vec<Item> {
+        self.entities
+            .iter()
+            .filter_map(|e| {
+                Some(Item {
+                    kind: e.item()?,
+                    position: Vec3::new(e.x as f32, e.y as f32, e.z as f32),
+                    spawned: true,
+                })
+            })
+            .collect()
+    }
+
+    pub fn spawns(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(|e| e.is_spawn())
+    }
+}
+
+fn parse_world(input: &mut Input, version: i32, sfactor: u32) -> Result<World, String> {
+    let size = 1usize << sfactor;
+    let mut cells: Vec<Cell> = Vec::with_capacity(size * size);
+
+    while cells.len() < size * size {
+        let kind = input.u8()?;
+        match kind {
+            CELL_RUN => {
+                let previous = *cells
+                    .last()
+                    .ok_or_else(|| String::from("cell run with no previous cell"))?;
+                let count = input.u8()? as usize;
+                for _ in 0..count {
+                    cells.push(previous);
+                }
+            }
+            CELL_RELIT if version <= 2 => {
+                // Same as the previous cell but with different lighting, which we don't keep.
+                let previous = *cells
+                    .last()
+                    .ok_or_else(|| String::from("relit cell with no previous cell"))?;
+                input.bytes(2)?;
+                cells.push(previous);
+            }
+            0 => {
+                // Solid cells only store their textures and vertex delta.
+                input.u8()?; // wall texture
+                let vdelta = input.u8()?;
+                if version <= 2 {
+                    input.bytes(2)?;
+                }
+                cells.push(Cell {
+                    kind: CellKind::Solid,
+                    floor: 0,
+                    ceil: 16,
+                    vdelta,
+                });
+            }
+            1..=5 => {
+                let floor = input.u8()? as i8;
+                let ceil = input.u8()? as i8;
+                input.bytes(3)?; // wall, floor and ceiling textures
+                if version <= 2 {
+                    input.bytes(2)?;
+                }
+                let vdelta = input.u8()?;
+                if version >= 2 {
+                    input.u8()?; // upper wall texture
+                }
+                if version >= 5 {
+                    input.u8()?; // tag
+                }
+
+                // Very old maps could have floors at or above their ceilings; the game fixes them
+                // up the same way when loading.
+                let floor = if floor >= ceil {
+                    ceil.saturating_sub(1)
+                } else {
+                    floor
+                };
+
+                cells.push(Cell {
+                    kind: CellKind::from_raw(kind),
+                    floor,
+                    ceil,
+                    vdelta,
+                });
+            }
+            _ => return Err(format!("invalid cell type: {}", kind)),
+        }
+    }
+
+    // A run may overshoot the end of the map; the game ignores the excess too.
+    cells.truncate(size * size);
+
+    World::new(sfactor, cells)
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes_at(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| String::from("unexpected end of map data"))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes_at(self.pos, len)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn i32_at(&self, offset: usize) -> Result<i32, String> {
+        let raw = self.bytes_at(offset, 4)?;
+        Ok(i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TITLE: &[u8] = b"Test map";
+
+    /// Build an uncompressed map with the header fields filled in and `header_size` bytes of
+    /// header, optionally ending with a water level and revision
+    fn map(
+        version: i32,
+        header_size: usize,
+        sfactor: i32,
+        entities: &[Vec<u8>],
+        cells: &[u8],
+    ) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"ACMP");
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&(header_size as i32).to_le_bytes());
+        data.extend_from_slice(&sfactor.to_le_bytes());
+        data.extend_from_slice(&(entities.len() as i32).to_le_bytes());
+        data.extend_from_slice(TITLE);
+        data.resize(header_size, 0);
+        if header_size >= HEADER_REVISION_OFFSET + 4 {
+            data[HEADER_WATERLEVEL_OFFSET..][..4].copy_from_slice(&(-3i32).to_le_bytes());
+            data[HEADER_REVISION_OFFSET..][..4].copy_from_slice(&7i32.to_le_bytes());
+        }
+        for entity in entities {
+            data.extend_from_slice(entity);
+        }
+        data.extend_from_slice(cells);
+        data
+    }
+
+    /// An entity as stored by `version`, with the extra attributes of version 10 set to garbage
+    fn entity(version: i32, kind: u8, x: i16, y: i16, z: i16, attr1: i16, attr2: u8) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for value in [x, y, z, attr1] {
+            raw.extend_from_slice(&value.to_le_bytes());
+        }
+        raw.extend_from_slice(&[kind, attr2, 0xaa, 0xbb]);
+        if version >= 10 {
+            raw.extend_from_slice(&[0xcc; 4]);
+        }
+        raw
+    }
+
+    /// A 4x4 map of version 6 or later: a solid cell, a run of 14 more, then an open cell
+    const SOLID_THEN_SPACE: &[u8] = &[
+        0, 1, 0, // solid: wall texture, vdelta
+        CELL_RUN, 14, // the previous cell 14 more times
+        4, 2, 20, 1, 2, 3, 5, 4, 0, // space: floor, ceil, textures, vdelta, upper wall, tag
+    ];
+
+    #[test]
+    fn header_fields() {
+        let data = map(10, HEADER_REVISION_OFFSET + 4, 2, &[], SOLID_THEN_SPACE);
+        let parsed = Map::parse_uncompressed(&data).unwrap();
+        assert_eq!(parsed.version, 10);
+        assert_eq!(parsed.title, "Test map");
+        assert_eq!(parsed.water_level, Some(-3));
+        assert_eq!(parsed.revision, Some(7));
+        assert_eq!(parsed.world.sfactor, 2);
+
+        // Older, shorter headers end before the optional fields.
+        let data = map(6, HEADER_MIN_SIZE, 2, &[], SOLID_THEN_SPACE);
+        let parsed = Map::parse_uncompressed(&data).unwrap();
+        assert_eq!(parsed.water_level, None);
+        assert_eq!(parsed.revision, None);
+    }
+
+    #[test]
+    fn entities_grow_in_version_10() {
+        for version in [9, 10] {
+            let entities = [
+                entity(
+                    version,
+                    ENTITY_PLAYERSTART,
+                    10,
+                    20,
+                    3,
+                    90,
+                    game::TEAM_RVSF as u8,
+                ),
+                entity(version, ENTITY_PLAYERSTART, 30, 40, 3, 180, 100),
+                entity(version, 6, 5, 6, 7, 0, 0),
+            ];
+            let data = map(version, HEADER_MIN_SIZE, 2, &entities, SOLID_THEN_SPACE);
+            let parsed = Map::parse_uncompressed(&data).unwrap();
+
+            let spawns: Vec<(i16, i16, i16, Option<i32>)> = parsed
+                .spawns()
+                .map(|e| (e.x, e.y, e.attr1, e.spawn_team()))
+                .collect();
+            assert_eq!(
+                spawns,
+                [(10, 20, 90, Some(game::TEAM_RVSF)), (30, 40, 180, None)],
+                "version {}",
+                version
+            );
+
+            let items = parsed.items();
+            assert_eq!(items.len(), 1);
+            assert_eq!(items[0].kind, ItemKind::Health);
+            assert_eq!(items[0].position, Vec3::new(5.0, 6.0, 7.0));
+            // The geometry after the entities still lines up.
+            assert_eq!(parsed.world.cells[15].kind, CellKind::Space);
+        }
+    }
+
+    #[test]
+    fn cell_runs_repeat_the_previous_cell() {
+        let data = map(6, HEADER_MIN_SIZE, 2, &[], SOLID_THEN_SPACE);
+        let cells = Map::parse_uncompressed(&data).unwrap().world.cells;
+        assert_eq!(cells.len(), 16);
+        assert!(cells[..15]
+            .iter()
+            .all(|c| c.kind == CellKind::Solid && c.vdelta == 0));
+        assert_eq!(
+            cells[15],
+            Cell {
+                kind: CellKind::Space,
+                floor: 2,
+                ceil: 20,
+                vdelta: 5,
+            }
+        );
+
+        // A run that overshoots the map is cut off.
+        let data = map(6, HEADER_MIN_SIZE, 2, &[], &[0, 1, 0, CELL_RUN, 200]);
+        assert_eq!(
+            Map::parse_uncompressed(&data).unwrap().world.cells.len(),
+            16
+        );
+
+        // A run needs a cell to repeat.
+        let data = map(6, HEADER_MIN_SIZE, 2, &[], &[CELL_RUN, 16]);
+        assert!(Map::parse_uncompressed(&data).is_err());
+    }
+
+    #[test]
+    fn truncated_maps_fail() {
+        let entities = [entity(10, ENTITY_PLAYERSTART, 1, 2, 3, 0, 0)];
+        let data = map(10, HEADER_MIN_SIZE, 2, &entities, SOLID_THEN_SPACE);
+        assert!(Map::parse_uncompressed(&data).is_ok());
+        for len in 0..data.len() {
+            assert!(
+                Map::parse_uncompressed(&data[..len]).is_err(),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn garbage_fails() {
+        let good = map(10, HEADER_MIN_SIZE, 2, &[], SOLID_THEN_SPACE);
+        let with = |offset: usize, value: i32| {
+            let mut data = good.clone();
+            data[offset..][..4].copy_from_slice(&value.to_le_bytes());
+            Map::parse_uncompressed(&data)
+        };
+
+        assert!(with(0, 0x1234_5678).is_err());
+        assert!(with(HEADER_SIZE_OFFSET, -1).is_err());
+        assert!(with(HEADER_SIZE_OFFSET, 20).is_err());
+        assert!(with(HEADER_SIZE_OFFSET, good.len() as i32 + 1).is_err());
+        assert!(with(HEADER_SFACTOR_OFFSET, 11).is_err());
+        assert!(with(HEADER_NUMENTS_OFFSET, -1).is_err());
+        assert!(with(HEADER_NUMENTS_OFFSET, 1000).is_err());
+
+        let bad_cell = map(10, HEADER_MIN_SIZE, 2, &[], &[9]);
+        assert_eq!(
+            Map::parse_uncompressed(&bad_cell).err(),
+            Some(String::from("invalid cell type: 9"))
+        );
+        assert!(Map::parse_uncompressed(b"\x1f\x8b garbage").is_err());
+    }
+}
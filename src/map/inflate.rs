@@ -0,0 +1,500 @@
+//! A small, dependency-free implementation of DEFLATE decompression (RFC 1951) and the gzip
+//! container around it (RFC 1952)
+//!
+//! Speed isn't a concern for the handful of map files we read, so this follows the simple
+//! canonical-Huffman approach of zlib's "puff" rather than building lookup tables.
+
+const MAX_BITS: usize = 15;
+const MAX_LENGTH_CODES: usize = 286;
+const MAX_DIST_CODES: usize = 30;
+const FIXED_LENGTH_CODES: usize = 288;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order in which code length code lengths are stored in a dynamic block header
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const GZIP_DEFLATE: u8 = 8;
+const GZIP_FHCRC: u8 = 0x02;
+const GZIP_FEXTRA: u8 = 0x04;
+const GZIP_FNAME: u8 = 0x08;
+const GZIP_FCOMMENT: u8 = 0x10;
+
+/// Decompress a gzip file, verifying its checksum and length
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    if data.len() < 18 || data[..2] != GZIP_MAGIC {
+        return Err(String::from("not a gzip file"));
+    }
+    if data[2] != GZIP_DEFLATE {
+        return Err(format!("unsupported gzip compression method: {}", data[2]));
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+
+    if flags & GZIP_FEXTRA != 0 {
+        let len = u16::from_le_bytes([byte(data, pos)?, byte(data, pos + 1)?]) as usize;
+        pos += 2 + len;
+    }
+    if flags & GZIP_FNAME != 0 {
+        pos = skip_cstr(data, pos)?;
+    }
+    if flags & GZIP_FCOMMENT != 0 {
+        pos = skip_cstr(data, pos)?;
+    }
+    if flags & GZIP_FHCRC != 0 {
+        pos += 2;
+    }
+
+    if pos > data.len() {
+        return Err(String::from("truncated gzip header"));
+    }
+
+    let (out, used) = inflate_with_len(&data[pos..])?;
+
+    let trailer = pos + used;
+    if trailer + 8 > data.len() {
+        return Err(String::from("truncated gzip trailer"));
+    }
+    let crc = u32::from_le_bytes([
+        data[trailer],
+        data[trailer + 1],
+        data[trailer + 2],
+        data[trailer + 3],
+    ]);
+    let size = u32::from_le_bytes([
+        data[trailer + 4],
+        data[trailer + 5],
+        data[trailer + 6],
+        data[trailer + 7],
+    ]);
+
+    if crc32(&out) != crc {
+        return Err(String::from("gzip checksum mismatch"));
+    }
+    if out.len() as u32 != size {
+        return Err(String::from("gzip length mismatch"));
+    }
+
+    Ok(out)
+}
+
+/// Decompress a raw DEFLATE stream, also returning how many input bytes it took up
+fn inflate_with_len(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut input = Bits::new(data);
+    let mut out = Vec::with_capacity(data.len() * 4);
+
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => stored(&mut input, &mut out)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                codes(&mut input, &mut out, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut input)?;
+                codes(&mut input, &mut out, &lengths, &distances)?;
+            }
+            _ => return Err(String::from("invalid deflate block type")),
+        }
+
+        if last {
+            break;
+        }
+    }
+
+    Ok((out, input.consumed()))
+}
+
+/// Reads a byte slice as a stream of bits, least significant bit first
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Bits<'a> {
+        Bits {
+            data,
+            pos: 0,
+            buf: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, String> {
+        while self.count < n {
+            let next = *self
+                .data
+                .get(self.pos)
+                .ok_or_else(|| String::from("unexpected end of deflate stream"))?;
+            self.buf |= (next as u32) << self.count;
+            self.pos += 1;
+            self.count += 8;
+        }
+
+        let value = self.buf & ((1u64 << n) - 1) as u32;
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Discard bits up to the next byte boundary
+    fn align(&mut self) {
+        self.buf = 0;
+        self.count = 0;
+    }
+
+    fn consumed(&self) -> usize {
+        self.pos
+    }
+}
+
+/// A canonical Huffman code, described by how many codes there are of each length and the
+/// symbols ordered by code
+struct Huffman {
+    counts: [u16; MAX_BITS + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, String> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+
+        // Check the code isn't over-subscribed. Incomplete codes are allowed; decoding one of the
+        // missing codes is reported as an error instead.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left <<= 1;
+            left -= count as i32;
+            if left < 0 {
+                return Err(String::from("over-subscribed huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut Bits) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for len in 1..=MAX_BITS {
+            code |= input.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + (code - first)) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+
+        Err(String::from("invalid huffman code"))
+    }
+}
+
+fn stored(input: &mut Bits, out: &mut Vec<u8>) -> Result<(), String> {
+    input.align();
+
+    let header = input
+        .data
+        .get(input.pos..input.pos + 4)
+        .ok_or_else(|| String::from("truncated stored block"))?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    let nlen = u16::from_le_bytes([header[2], header[3]]);
+    if len != !nlen {
+        return Err(String::from("stored block length mismatch"));
+    }
+    input.pos += 4;
+
+    let block = input
+        .data
+        .get(input.pos..input.pos + len as usize)
+        .ok_or_else(|| String::from("truncated stored block"))?;
+    out.extend_from_slice(block);
+    input.pos += len as usize;
+
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; FIXED_LENGTH_CODES];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+
+    // These are known to be valid, so construction can't fail.
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; MAX_DIST_CODES]).unwrap(),
+    )
+}
+
+fn dynamic_codes(input: &mut Bits) -> Result<(Huffman, Huffman), String> {
+    let length_count = input.bits(5)? as usize + 257;
+    let dist_count = input.bits(5)? as usize + 1;
+    let code_count = input.bits(4)? as usize + 4;
+
+    if length_count > MAX_LENGTH_CODES || dist_count > MAX_DIST_CODES {
+        return Err(String::from("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[index] = input.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    // Literal/length and distance code lengths are run-length encoded as one sequence.
+    let mut lengths = vec![0u8; length_count + dist_count];
+    let mut index = 0;
+    while index < lengths.len() {
+        let symbol = code_lengths.decode(input)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if index == 0 {
+                    return Err(String::from("repeated code length with no previous length"));
+                }
+                (lengths[index - 1], 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+
+        if index + repeat > lengths.len() {
+            return Err(String::from("too many code lengths"));
+        }
+        for length in &mut lengths[index..index + repeat] {
+            *length = value;
+        }
+        index += repeat;
+    }
+
+    if lengths[256] == 0 {
+        return Err(String::from("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..length_count])?,
+        Huffman::new(&lengths[length_count..])?,
+    ))
+}
+
+fn codes(
+    input: &mut Bits,
+    out: &mut Vec<u8>,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), String> {
+    loop {
+        let symbol = lengths.decode(input)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let symbol = symbol - 257;
+                if symbol >= LENGTH_BASE.len() {
+                    return Err(String::from("invalid length symbol"));
+                }
+                let len = LENGTH_BASE[symbol] as usize
+                    + input.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(input)? as usize;
+                if symbol >= DIST_BASE.len() {
+                    return Err(String::from("invalid distance symbol"));
+                }
+                let dist =
+                    DIST_BASE[symbol] as usize + input.bits(DIST_EXTRA[symbol] as u32)? as usize;
+
+                if dist > out.len() {
+                    return Err(String::from("distance reaches before start of output"));
+                }
+
+                // The source and destination may overlap, in which case bytes written by this copy
+                // are read again further along it, so copy one byte at a time.
+                let start = out.len() - dist;
+                for i in 0..len {
+                    let b = out[start + i];
+                    out.push(b);
+                }
+            }
+        }
+    }
+}
+
+/// The CRC-32 used by gzip (and zip, and PNG, ...)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+
+    let mut crc = !0u32;
+    for &b in data {
+        crc = table[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+fn byte(data: &[u8], pos: usize) -> Result<u8, String> {
+    data.get(pos)
+        .copied()
+        .ok_or_else(|| String::from("truncated gzip header"))
+}
+
+fn skip_cstr(data: &[u8], pos: usize) -> Result<usize, String> {
+    let len = data
+        .get(pos..)
+        .and_then(|rest| rest.iter().position(|&c| c == 0))
+        .ok_or_else(|| String::from("truncated gzip header"))?;
+    Ok(pos + len + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "stored" in a single stored block
+    const STORED: &[u8] = &[
+        0x01, 0x06, 0x00, 0xf9, 0xff, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x64,
+    ];
+
+    /// "AssaultCube AssaultCube AssaultCube" in a single fixed-Huffman block
+    const FIXED: &[u8] = &[
+        0x73, 0x2c, 0x2e, 0x4e, 0x2c, 0xcd, 0x29, 0x71, 0x2e, 0x4d, 0x4a, 0x55, 0x70, 0xc4, 0xce,
+        0x06, 0x00,
+    ];
+
+    /// `DIGITS` in a single dynamic-Huffman block
+    const DYNAMIC: &[u8] = &[
+        0x05, 0xc1, 0x01, 0x01, 0x00, 0x30, 0x08, 0xc3, 0x30, 0x4b, 0xdb, 0xe1, 0x8c, 0xfa, 0x37,
+        0x46, 0x22, 0xcc, 0x64, 0x5e, 0x1c, 0x35, 0xf9, 0xdb, 0xe5, 0xf5, 0x74, 0x81, 0x64, 0x38,
+    ];
+    const DIGITS: &[u8] = b"09196762717049758431816439900199";
+
+    /// `FIXED` in a gzip container
+    const GZIPPED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x73, 0x2c, 0x2e, 0x4e, 0x2c,
+        0xcd, 0x29, 0x71, 0x2e, 0x4d, 0x4a, 0x55, 0x70, 0xc4, 0xce, 0x06, 0x00, 0x38, 0x2f, 0x3a,
+        0x7d, 0x23, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(
+            inflate_with_len(STORED).unwrap(),
+            (b"stored".to_vec(), STORED.len())
+        );
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(
+            inflate_with_len(FIXED).unwrap(),
+            (b"AssaultCube AssaultCube AssaultCube".to_vec(), FIXED.len())
+        );
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!(
+            inflate_with_len(DYNAMIC).unwrap(),
+            (DIGITS.to_vec(), DYNAMIC.len())
+        );
+    }
+
+    #[test]
+    fn truncated_streams_fail() {
+        for stream in [STORED, FIXED, DYNAMIC] {
+            for len in 0..stream.len() {
+                assert!(
+                    inflate_with_len(&stream[..len]).is_err(),
+                    "{:02x?} cut to {} bytes",
+                    stream,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_blocks_fail() {
+        // A last block of the reserved type 3
+        assert!(inflate_with_len(&[0x07]).is_err());
+        // A stored block whose length doesn't match its complement
+        assert!(inflate_with_len(&[0x01, 0x06, 0x00, 0x00, 0x00]).is_err());
+    }
+
+    #[test]
+    fn gunzip_checks_the_container() {
+        assert_eq!(
+            gunzip(GZIPPED).unwrap(),
+            b"AssaultCube AssaultCube AssaultCube"
+        );
+
+        assert!(gunzip(&GZIPPED[..GZIPPED.len() - 1]).is_err());
+        assert!(gunzip(&GZIPPED[1..]).is_err());
+
+        let mut corrupt = GZIPPED.to_vec();
+        let crc = GZIPPED.len() - 8;
+        corrupt[crc] ^= 1;
+        assert!(gunzip(&corrupt).is_err());
+    }
+
+    #[test]
+    fn crc32_matches_the_standard() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+}
@@ -0,0 +1,639 @@
+//! Vectors, view angles and projection, following the game's conventions
+//!
+//! The world is z-up. A yaw of 0 looks down the negative y axis and increases clockwise when seen
+//! from above, wrapping at 360; pitch is 0 at the horizon, positive looking up and limited to
+//! straight up or down.
+
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::proc::{self, ProcessMemory, Read};
+
+/// Where the client keeps the model-view-projection matrix used to render the last frame
+pub const VIEW_MATRIX_ADDR: proc::Address = 0x501ae8;
+pub const SCREEN_WIDTH_ADDR: proc::Address = 0x510c94;
+pub const SCREEN_HEIGHT_ADDR: proc::Address = 0x510c98;
+
+/// Points closer to the camera plane than this (in clip space) are treated as behind the camera,
+/// which also avoids dividing by a w of (almost) zero.
+const MIN_CLIP_W: f32 = 0.001;
+
+/// The steepest the game lets a player look up or down
+pub const MAX_PITCH: f32 = 90.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn length(self) -> f32 {
+        self.length_squared().sqrt()
+    }
+
+    /// The squared length, which is cheaper and just as good for comparing lengths
+    pub fn length_squared(self) -> f32 {
+        self.dot(self)
+    }
+
+    /// Length of the projection onto the ground plane
+    pub fn length_2d(self) -> f32 {
+        (self.x * self.x + self.y * self.y).sqrt()
+    }
+
+    pub fn distance(self, other: Vec3) -> f32 {
+        (other - self).length()
+    }
+
+    pub fn distance_squared(self, other: Vec3) -> f32 {
+        (other - self).length_squared()
+    }
+
+    /// The unit vector pointing the same way, or `None` for the zero vector
+    pub fn normalize(self) -> Option<Vec3> {
+        let length = self.length();
+        if length == 0.0 {
+            None
+        } else {
+            Some(self * (1.0 / length))
+        }
+    }
+
+    /// The angle between two vectors, in degrees; zero if either of them is the zero vector
+    pub fn angle_between(self, other: Vec3) -> f32 {
+        match (self.normalize(), other.normalize()) {
+            (Some(a), Some(b)) => a.dot(b).clamp(-1.0, 1.0).acos().to_degrees(),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, scale: f32) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// A view direction, in degrees
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Angles {
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Angles {
+    pub fn new(yaw: f32, pitch: f32) -> Angles {
+        Angles { yaw, pitch }
+    }
+
+    /// The view angles looking from one point at another
+    ///
+    /// Looking straight up or down leaves the yaw undefined; it's reported as 0. Looking at the
+    /// point you're standing on gives all zeroes.
+    pub fn towards(from: Vec3, to: Vec3) -> Angles {
+        let delta = to - from;
+        let base = delta.length_2d();
+
+        let yaw = if base == 0.0 {
+            0.0
+        } else {
+            normalize_yaw(delta.y.atan2(delta.x).to_degrees() + 90.0)
+        };
+
+        // atan2 rather than atan(z / base), so that base being zero gives +/-90 rather than NaN.
+        let pitch = if delta.z == 0.0 && base == 0.0 {
+            0.0
+        } else {
+            delta.z.atan2(base).to_degrees()
+        };
+
+        Angles { yaw, pitch }
+    }
+
+    /// The unit vector pointing in this direction; the inverse of `towards`
+    pub fn direction(self) -> Vec3 {
+        let yaw = (self.yaw - 90.0).to_radians();
+        let pitch = self.pitch.to_radians();
+        Vec3::new(
+            yaw.cos() * pitch.cos(),
+            yaw.sin() * pitch.cos(),
+            pitch.sin(),
+        )
+    }
+
+    /// Wrap the yaw into [0, 360) and clamp the pitch to what the game allows
+    pub fn normalize(self) -> Angles {
+        Angles {
+            yaw: normalize_yaw(self.yaw),
+            pitch: self.pitch.clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    /// How far the view has to turn to get from these angles to the others, in degrees
+    ///
+    /// This treats yaw and pitch as flat coordinates, which is what matters for how far the mouse
+    /// has to move; for the true angle between the view directions, use `Vec3::angle_between`.
+    pub fn distance(self, other: Angles) -> f32 {
+        let yaw = yaw_delta(self.yaw, other.yaw);
+        let pitch = other.pitch - self.pitch;
+        (yaw * yaw + pitch * pitch).sqrt()
+    }
+}
+
+/// The shortest signed turn from one yaw to another, in [-180, 180)
+pub fn yaw_delta(from: f32, to: f32) -> f32 {
+    normalize_yaw(to - from + 180.0) - 180.0
+}
+
+/// Wrap a yaw into [0, 360)
+pub fn normalize_yaw(yaw: f32) -> f32 {
+    let yaw = yaw.rem_euclid(360.0);
+    // rem_euclid can round up to exactly 360 for tiny negative inputs.
+    if yaw >= 360.0 {
+        0.0
+    } else {
+        yaw
+    }
+}
+
+/// An upright cylinder, standing on its base point
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub radius: f32,
+    pub height: f32,
+}
+
+impl Cylinder {
+    /// How far along a ray the ray first hits the cylinder, if it does at all
+    ///
+    /// `direction` must be a unit vector, making the result a distance. A ray starting inside the
+    /// cylinder hits it at 0.
+    pub fn intersect(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let (top, bottom) = (self.base.z + self.height, self.base.z);
+        let within_height = |t: f32| {
+            let z = origin.z + direction.z * t;
+            z >= bottom && z <= top
+        };
+
+        // The side: solve |(origin + t * direction - base).xy| = radius for t.
+        let (ox, oy) = (origin.x - self.base.x, origin.y - self.base.y);
+        let a = direction.x * direction.x + direction.y * direction.y;
+        let b = 2.0 * (ox * direction.x + oy * direction.y);
+        let c = ox * ox + oy * oy - self.radius * self.radius;
+
+        if c <= 0.0 && within_height(0.0) {
+            return Some(0.0);
+        }
+
+        let mut nearest: Option<f32> = None;
+        let mut consider = |t: f32| {
+            if t >= 0.0 && !matches!(nearest, Some(n) if n <= t) {
+                nearest = Some(t);
+            }
+        };
+
+        if a > 0.0 {
+            let discriminant = b * b - 4.0 * a * c;
+            if discriminant >= 0.0 {
+                let t = (-b - discriminant.sqrt()) / (2.0 * a);
+                if within_height(t) {
+                    consider(t);
+                }
+            }
+        }
+
+        // The caps, which are all a ray looking straight up or down can hit.
+        if direction.z != 0.0 {
+            for z in [bottom, top] {
+                let t = (z - origin.z) / direction.z;
+                let (x, y) = (ox + direction.x * t, oy + direction.y * t);
+                if x * x + y * y <= self.radius * self.radius {
+                    consider(t);
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+/// A 4x4 matrix stored in column-major order, as OpenGL expects it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4(pub [f32; 16]);
+
+impl Matrix4 {
+    /// Transform a world position into homogeneous clip space, returning (x, y, z, w)
+    pub fn transform(&self, position: Vec3) -> [f32; 4] {
+        let m = &self.0;
+        let Vec3 { x, y, z } = position;
+        [
+            m[0] * x + m[4] * y + m[8] * z + m[12],
+            m[1] * x + m[5] * y + m[9] * z + m[13],
+            m[2] * x + m[6] * y + m[10] * z + m[14],
+            m[3] * x + m[7] * y + m[11] * z + m[15],
+        ]
+    }
+
+    /// Project a world position onto the screen
+    ///
+    /// Returns `None` when the position is behind the camera. Positions in front of the camera but
+    /// outside the field of view are still projected, and end up outside the viewport; use
+    /// `ScreenPoint::on_screen` to tell them apart.
+    pub fn project(&self, position: Vec3, viewport: &Viewport) -> Option<ScreenPoint> {
+        let [clip_x, clip_y, clip_z, w] = self.transform(position);
+        if w < MIN_CLIP_W {
+            return None;
+        }
+
+        // Normalized device coordinates run from -1 to 1, with y pointing up; screen coordinates
+        // start in the top left corner with y pointing down.
+        let (ndc_x, ndc_y) = (clip_x / w, clip_y / w);
+        Some(ScreenPoint {
+            x: (ndc_x + 1.0) * viewport.width / 2.0,
+            y: (1.0 - ndc_y) * viewport.height / 2.0,
+            depth: clip_z / w,
+            within: viewport.contains(ndc_x, ndc_y),
+        })
+    }
+}
+
+impl Read for Matrix4 {
+    fn read<M: ProcessMemory>(mut memory: M, addr: proc::Address) -> Result<Matrix4, String> {
+        let raw = memory.read_bytes(addr, 16 * std::mem::size_of::<f32>())?;
+        if raw.len() != 16 * std::mem::size_of::<f32>() {
+            return Err(String::from("short read of matrix"));
+        }
+
+        let mut m = [0.0; 16];
+        for (value, bytes) in m.iter_mut().zip(raw.chunks_exact(4)) {
+            *value = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        Ok(Matrix4(m))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn center(&self) -> (f32, f32) {
+        (self.width / 2.0, self.height / 2.0)
+    }
+
+    fn contains(&self, ndc_x: f32, ndc_y: f32) -> bool {
+        (-1.0..=1.0).contains(&ndc_x) && (-1.0..=1.0).contains(&ndc_y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScreenPoint {
+    /// Pixels from the left edge of the viewport
+    pub x: f32,
+    /// Pixels from the top edge of the viewport
+    pub y: f32,
+    /// Normalized depth, from -1 at the near plane to 1 at the far plane
+    pub depth: f32,
+    within: bool,
+}
+
+impl ScreenPoint {
+    pub fn on_screen(&self) -> bool {
+        self.within
+    }
+
+    /// Distance in pixels from the center of the viewport, i.e. from the crosshair
+    pub fn crosshair_distance(&self, viewport: &Viewport) -> f32 {
+        let (cx, cy) = viewport.center();
+        ((self.x - cx).powi(2) + (self.y - cy).powi(2)).sqrt()
+    }
+}
+
+/// Read the model-view-projection matrix of the last rendered frame
+pub fn view_matrix(handle: proc::Handle) -> Result<Matrix4, String> {
+    Matrix4::read(handle, VIEW_MATRIX_ADDR)
+}
+
+/// Read the size of the game window's viewport
+pub fn viewport(handle: proc::Handle) -> Result<Viewport, String> {
+    let width = i32::read(handle, SCREEN_WIDTH_ADDR)?;
+    let height = i32::read(handle, SCREEN_HEIGHT_ADDR)?;
+    Ok(Viewport {
+        width: width as f32,
+        height: height as f32,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: Viewport = Viewport {
+        width: 800.0,
+        height: 600.0,
+    };
+
+    const IDENTITY: Matrix4 = Matrix4([
+        1.0, 0.0, 0.0, 0.0, //
+        0.0, 1.0, 0.0, 0.0, //
+        0.0, 0.0, 1.0, 0.0, //
+        0.0, 0.0, 0.0, 1.0, //
+    ]);
+
+    /// An OpenGL perspective projection looking down -z, with a 90 degree field of view, a square
+    /// aspect ratio and the near and far planes at 1 and 100
+    const PERSPECTIVE: Matrix4 = Matrix4([
+        1.0,
+        0.0,
+        0.0,
+        0.0, //
+        0.0,
+        1.0,
+        0.0,
+        0.0, //
+        0.0,
+        0.0,
+        -101.0 / 99.0,
+        -1.0, //
+        0.0,
+        0.0,
+        -200.0 / 99.0,
+        0.0, //
+    ]);
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    fn project(matrix: &Matrix4, x: f32, y: f32, z: f32) -> ScreenPoint {
+        matrix
+            .project(Vec3::new(x, y, z), &VIEWPORT)
+            .expect("projected behind the camera")
+    }
+
+    #[test]
+    fn identity_maps_the_unit_square_onto_the_screen() {
+        let center = project(&IDENTITY, 0.0, 0.0, 0.0);
+        assert_eq!((center.x, center.y), (400.0, 300.0));
+        assert!(center.on_screen());
+        assert_eq!(center.crosshair_distance(&VIEWPORT), 0.0);
+
+        let corner = project(&IDENTITY, 1.0, 1.0, 0.0);
+        assert_eq!((corner.x, corner.y), (800.0, 0.0));
+        assert!(corner.on_screen());
+        assert_eq!(corner.crosshair_distance(&VIEWPORT), 500.0);
+
+        let outside = project(&IDENTITY, 2.0, 0.0, 0.0);
+        assert_eq!(outside.x, 1200.0);
+        assert!(!outside.on_screen());
+    }
+
+    #[test]
+    fn perspective_divides_by_distance() {
+        let ahead = project(&PERSPECTIVE, 0.0, 0.0, -10.0);
+        assert_eq!((ahead.x, ahead.y), (400.0, 300.0));
+        assert_near(ahead.depth, 810.0 / 990.0);
+
+        // Half way to the edge of the field of view at this distance
+        let right = project(&PERSPECTIVE, 5.0, 0.0, -10.0);
+        assert_near(right.x, 600.0);
+        let up = project(&PERSPECTIVE, 0.0, 5.0, -10.0);
+        assert_near(up.y, 150.0);
+
+        // Twice as far away, the same offset is half as far from the center.
+        let further = project(&PERSPECTIVE, 5.0, 0.0, -20.0);
+        assert_near(further.x, 500.0);
+
+        let wide = project(&PERSPECTIVE, 20.0, 0.0, -10.0);
+        assert_near(wide.x, 1200.0);
+        assert!(!wide.on_screen());
+    }
+
+    #[test]
+    fn perspective_depth_spans_near_to_far() {
+        assert_near(project(&PERSPECTIVE, 0.0, 0.0, -1.0).depth, -1.0);
+        assert_near(project(&PERSPECTIVE, 0.0, 0.0, -100.0).depth, 1.0);
+    }
+
+    #[test]
+    fn behind_the_camera_is_not_projected() {
+        assert_eq!(
+            PERSPECTIVE.project(Vec3::new(0.0, 0.0, 10.0), &VIEWPORT),
+            None
+        );
+        // In the camera plane, where w is zero
+        assert_eq!(
+            PERSPECTIVE.project(Vec3::new(5.0, 0.0, 0.0), &VIEWPORT),
+            None
+        );
+    }
+
+    #[test]
+    fn cylinder_intersect_finds_the_nearest_hit() {
+        let cylinder = Cylinder {
+            base: Vec3::ZERO,
+            radius: 1.0,
+            height: 5.0,
+        };
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let cases = [
+            // Through the side
+            (Vec3::new(-10.0, 0.0, 2.0), x, Some(9.0)),
+            // Past the side, over the top and away from it
+            (Vec3::new(-10.0, 5.0, 2.0), x, None),
+            (Vec3::new(-10.0, 0.0, 6.0), x, None),
+            (Vec3::new(10.0, 0.0, 2.0), x, None),
+            // Down through the top
+            (
+                Vec3::new(0.0, 0.0, 10.0),
+                Vec3::new(0.0, 0.0, -1.0),
+                Some(5.0),
+            ),
+            // From inside
+            (
+                Vec3::new(0.5, 0.0, 1.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Some(0.0),
+            ),
+        ];
+        for &(origin, direction, expected) in &cases {
+            let hit = cylinder.intersect(origin, direction);
+            match (hit, expected) {
+                (Some(hit), Some(expected)) => assert_near(hit, expected),
+                (hit, expected) => assert_eq!(hit, expected, "ray from {:?}", origin),
+            }
+        }
+    }
+
+    /// A fixed stream of pseudo-random numbers, so that failures can be reproduced
+    struct Numbers(u32);
+
+    impl Numbers {
+        fn new() -> Numbers {
+            Numbers(0x2545_f491)
+        }
+
+        /// The next number in [low, high)
+        fn next(&mut self, low: f32, high: f32) -> f32 {
+            // xorshift32
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            low + (high - low) * (self.0 >> 8) as f32 / (1 << 24) as f32
+        }
+    }
+
+    const SAMPLES: usize = 10_000;
+
+    #[test]
+    fn direction_and_towards_round_trip() {
+        let mut numbers = Numbers::new();
+        for _ in 0..SAMPLES {
+            // Straight up and down are left out, since they lose the yaw.
+            let angles = Angles::new(numbers.next(0.0, 360.0), numbers.next(-89.0, 89.0));
+            let direction = angles.direction();
+            assert!((direction.length() - 1.0).abs() < 1e-4, "{:?}", angles);
+
+            let from = Vec3::new(
+                numbers.next(-500.0, 500.0),
+                numbers.next(-500.0, 500.0),
+                numbers.next(-50.0, 50.0),
+            );
+            let to = from + direction * numbers.next(1.0, 500.0);
+            let back = Angles::towards(from, to);
+            assert!(
+                angles.distance(back) < 1e-2,
+                "{:?} came back as {:?}",
+                angles,
+                back
+            );
+        }
+
+        let up = Angles::towards(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(up, Angles::new(0.0, 90.0));
+        assert_eq!(Angles::towards(Vec3::ZERO, Vec3::ZERO), Angles::default());
+    }
+
+    #[test]
+    fn normalize_yaw_stays_in_range() {
+        let mut numbers = Numbers::new();
+        let edges = [-720.5, -360.0, -1e-6, -0.0, 0.0, 359.9999, 360.0, 1e6];
+        let generated = (0..SAMPLES).map(|_| numbers.next(-10_000.0, 10_000.0));
+        for yaw in edges.iter().copied().chain(generated) {
+            let normalized = normalize_yaw(yaw);
+            assert!(
+                (0.0..360.0).contains(&normalized),
+                "{} became {}",
+                yaw,
+                normalized
+            );
+            assert!(
+                yaw_delta(yaw, normalized).abs() < 1e-2,
+                "{} became {}",
+                yaw,
+                normalized
+            );
+        }
+    }
+
+    #[test]
+    fn yaw_delta_is_the_shortest_turn() {
+        let mut numbers = Numbers::new();
+        for _ in 0..SAMPLES {
+            let from = numbers.next(-1080.0, 1080.0);
+            let to = numbers.next(-1080.0, 1080.0);
+            let delta = yaw_delta(from, to);
+            assert!(
+                (-180.0..180.0).contains(&delta),
+                "{} to {} is {}",
+                from,
+                to,
+                delta
+            );
+
+            // Turning back is the same turn the other way, except at exactly half a turn, where
+            // both ways round are -180.
+            let back = yaw_delta(to, from);
+            let sum = delta + back;
+            assert!(
+                sum.abs() < 1e-2 || (sum + 360.0).abs() < 1e-2,
+                "{} and {}",
+                delta,
+                back
+            );
+
+            let landed = yaw_delta(normalize_yaw(from + delta), to);
+            assert!(
+                landed.abs() < 1e-2,
+                "turning {} from {} missed {}",
+                delta,
+                from,
+                to
+            );
+        }
+    }
+
+    #[test]
+    fn yaw_delta_wraps_across_half_a_turn() {
+        assert_near(yaw_delta(170.0, -170.0), 20.0);
+        assert_near(yaw_delta(-170.0, 170.0), -20.0);
+        assert_near(yaw_delta(350.0, 10.0), 20.0);
+        assert_near(yaw_delta(10.0, 350.0), -20.0);
+        assert_eq!(yaw_delta(0.0, 180.0), -180.0);
+        assert_eq!(yaw_delta(0.0, -180.0), -180.0);
+        // Just short of half a turn the other way mustn't round up to +180.
+        for &tiny in &[1e-6, 1e-5, 1e-4] {
+            let delta = yaw_delta(10.0, -170.0 - tiny);
+            assert!((-180.0..180.0).contains(&delta), "{}", delta);
+        }
+    }
+}
@@ -0,0 +1,347 @@
+//! Machine-readable output, for piping the game state into other tools
+//!
+//! Each tick makes one `Record`. As JSON, that's one object per line. As CSV, it's one row under a
+//! fixed header: the local player gets columns of its own, while the other players, items and
+//! events, whose number changes from tick to tick, are nested in a column each as JSON arrays.
+//! Everything else the trainer has to say goes to stderr, so stdout stays clean.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::entities::{Item, Player};
+use crate::events::Event;
+
+/// How the player and item tables are printed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Fixed-width text, for reading
+    Table,
+    /// One JSON object per line and tick
+    Json,
+    /// Comma-separated values, one row per tick
+    Csv,
+    /// A full-screen view that redraws in place; see `dashboard`
+    Dashboard,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, String> {
+        match name {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "dashboard" => Ok(Format::Dashboard),
+            _ => Err(format!("unknown output format: {}", name)),
+        }
+    }
+}
+
+pub const CSV_HEADER: &str =
+    "timestamp,name,health,armor,team,x,y,z,yaw,pitch,players,items,events";
+
+/// How many columns the local player takes up in a CSV row
+const CSV_ME_COLUMNS: usize = 9;
+
+/// What was seen in one tick
+pub struct Record<'a> {
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u128,
+    pub me: Option<&'a Player>,
+    pub players: Vec<&'a Player>,
+    pub items: &'a [Item],
+    pub events: &'a [Event],
+}
+
+impl<'a> Record<'a> {
+    /// An empty record, timestamped now
+    pub fn now() -> Record<'a> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis())
+            .unwrap_or_default();
+        Record {
+            timestamp,
+            me: None,
+            players: Vec::new(),
+            items: &[],
+            events: &[],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.me.is_none()
+            && self.players.is_empty()
+            && self.items.is_empty()
+            && self.events.is_empty()
+    }
+
+    /// The record as a single line of JSON
+    pub fn to_json(&self) -> String {
+        let me = match self.me {
+            Some(me) => player_json(me),
+            None => String::from("null"),
+        };
+        format!(
+            "{{\"timestamp\":{},\"me\":{},\"players\":{},\"items\":{},\"events\":{}}}",
+            self.timestamp,
+            me,
+            self.players_json(),
+            self.items_json(),
+            self.events_json(),
+        )
+    }
+
+    /// The record as one CSV row ending in a newline, in the columns of `CSV_HEADER`
+    pub fn to_csv(&self) -> String {
+        let mut fields = vec![self.timestamp.to_string()];
+        match self.me {
+            Some(me) => {
+                let p = me.position;
+                fields.extend(vec![
+                    csv_field(&me.name),
+                    me.health.to_string(),
+                    me.armor.to_string(),
+                    me.team.to_string(),
+                    p.x.to_string(),
+                    p.y.to_string(),
+                    p.z.to_string(),
+                    me.view.yaw.to_string(),
+                    me.view.pitch.to_string(),
+                ]);
+            }
+            None => fields.resize(1 + CSV_ME_COLUMNS, String::new()),
+        }
+        fields.push(csv_field(&self.players_json()));
+        fields.push(csv_field(&self.items_json()));
+        fields.push(csv_field(&self.events_json()));
+        fields.join(",") + "\n"
+    }
+
+    fn players_json(&self) -> String {
+        json_array(self.players.iter().map(|p| player_json(p)))
+    }
+
+    fn items_json(&self) -> String {
+        json_array(self.items.iter().map(item_json))
+    }
+
+    fn events_json(&self) -> String {
+        json_array(self.events.iter().map(event_json))
+    }
+}
+
+fn player_json(player: &Player) -> String {
+    let slot = match player.slot {
+        Some(slot) => slot.to_string(),
+        None => String::from("null"),
+    };
+    format!(
+        "{{\"slot\":{},\"name\":{},\"health\":{},\"armor\":{},\"team\":{},\"crouching\":{},\
+         \"position\":{},\"yaw\":{},\"pitch\":{}}}",
+        slot,
+        json_string(&player.name),
+        player.health,
+        player.armor,
+        player.team,
+        player.crouching,
+        position_json(player.position.x, player.position.y, player.position.z),
+        json_number(player.view.yaw),
+        json_number(player.view.pitch),
+    )
+}
+
+fn item_json(item: &Item) -> String {
+    format!(
+        "{{\"kind\":{},\"spawned\":{},\"position\":{}}}",
+        json_string(item.kind.name()),
+        item.spawned,
+        position_json(item.position.x, item.position.y, item.position.z),
+    )
+}
+
+fn event_json(event: &Event) -> String {
+    let details = match event {
+        Event::Damaged { amount, .. } => format!(",\"amount\":{}", amount),
+        Event::ArmorChanged { from, to, .. } => format!(",\"from\":{},\"to\":{}", from, to),
+        Event::Renamed { from, .. } => format!(",\"from\":{}", json_string(from)),
+        Event::Teleported { distance, .. } => format!(",\"distance\":{}", json_number(*distance)),
+        _ => String::new(),
+    };
+    format!(
+        "{{\"type\":\"{}\",\"slot\":{},\"name\":{}{}}}",
+        event.kind(),
+        event.slot(),
+        json_string(event.name()),
+        details,
+    )
+}
+
+fn json_array<I: Iterator<Item = String>>(values: I) -> String {
+    format!("[{}]", values.collect::<Vec<_>>().join(","))
+}
+
+fn position_json(x: f32, y: f32, z: f32) -> String {
+    format!("[{},{},{}]", json_number(x), json_number(y), json_number(z))
+}
+
+/// JSON has no NaN or infinity, so those become null
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        String::from("null")
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quote a field if it contains anything that would otherwise break up the row
+fn csv_field(text: &str) -> String {
+    if text.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        String::from(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::ItemKind;
+    use crate::math::{Angles, Vec3};
+
+    fn me() -> Player {
+        Player {
+            name: String::from("me"),
+            health: 100,
+            position: Vec3::new(1.0, 2.0, 3.5),
+            view: Angles::new(90.0, -5.0),
+            ..Player::default()
+        }
+    }
+
+    fn other() -> Player {
+        Player {
+            slot: Some(2),
+            name: String::from("Jörg, \"the\" best"),
+            health: 40,
+            armor: 25,
+            team: 1,
+            crouching: true,
+            ..Player::default()
+        }
+    }
+
+    fn items() -> Vec<Item> {
+        vec![Item {
+            kind: ItemKind::Health,
+            position: Vec3::new(10.0, 20.0, 0.0),
+            spawned: false,
+        }]
+    }
+
+    fn events() -> Vec<Event> {
+        vec![Event::Damaged {
+            slot: 2,
+            name: String::from("Jörg, \"the\" best"),
+            amount: 60,
+        }]
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a \"b\" \\c"), "\"a \\\"b\\\" \\\\c\"");
+        assert_eq!(json_string("1\n2\r3\t4"), "\"1\\n2\\r3\\t4\"");
+        assert_eq!(json_string("\u{1}\u{1f}"), "\"\\u0001\\u001f\"");
+        // Anything from Latin-1 that decoding kept is valid JSON as it is.
+        assert_eq!(json_string("Jörg ©"), "\"Jörg ©\"");
+    }
+
+    #[test]
+    fn json_numbers_are_finite() {
+        assert_eq!(json_number(1.5), "1.5");
+        assert_eq!(json_number(f32::NAN), "null");
+        assert_eq!(json_number(f32::INFINITY), "null");
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("Jörg"), "Jörg");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn json_record() {
+        let (me, other, items, events) = (me(), other(), items(), events());
+        let record = Record {
+            timestamp: 1234,
+            me: Some(&me),
+            players: vec![&other],
+            items: &items,
+            events: &events,
+        };
+        assert_eq!(
+            record.to_json(),
+            concat!(
+                r#"{"timestamp":1234,"#,
+                r#""me":{"slot":null,"name":"me","health":100,"armor":0,"team":0,"#,
+                r#""crouching":false,"position":[1,2,3.5],"yaw":90,"pitch":-5},"#,
+                r#""players":[{"slot":2,"name":"Jörg, \"the\" best","health":40,"armor":25,"#,
+                r#""team":1,"crouching":true,"position":[0,0,0],"yaw":0,"pitch":0}],"#,
+                r#""items":[{"kind":"Health","spawned":false,"position":[10,20,0]}],"#,
+                r#""events":[{"type":"damaged","slot":2,"name":"Jörg, \"the\" best","amount":60}]}"#,
+            )
+        );
+
+        let empty = Record::now();
+        assert!(empty.is_empty());
+        assert!(empty
+            .to_json()
+            .ends_with(r#""me":null,"players":[],"items":[],"events":[]}"#));
+    }
+
+    #[test]
+    fn csv_record_is_one_row() {
+        let (me, other, items, events) = (me(), other(), items(), events());
+        let record = Record {
+            timestamp: 1234,
+            me: Some(&me),
+            players: vec![&other],
+            items: &items,
+            events: &events,
+        };
+        let row = record.to_csv();
+        assert_eq!(row.matches('\n').count(), 1);
+        assert!(row.starts_with("1234,me,100,0,0,1,2,3.5,90,-5,\"[{\"\"slot\"\":2,"));
+        assert!(row.ends_with(",\"[{\"\"type\"\":\"\"damaged\"\",\"\"slot\"\":2,\"\"name\"\":\"\"Jörg, \\\"\"the\\\"\" best\"\",\"\"amount\"\":60}]\"\n"));
+
+        let empty = Record {
+            timestamp: 1,
+            ..Record::now()
+        };
+        assert_eq!(empty.to_csv(), "1,,,,,,,,,,[],[],[]\n");
+        assert_eq!(
+            CSV_HEADER.split(',').count(),
+            empty.to_csv().trim_end().split(',').count()
+        );
+    }
+}
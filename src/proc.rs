@@ -1,5 +1,10 @@
 //! Safe abstractions over the Windows API for interacting with remote processes
 
+#[cfg(test)]
+pub mod fake;
+pub mod pattern;
+pub mod pe;
+
 use crate::winapi;
 use crate::winapi_error;
 
@@ -7,43 +12,114 @@ pub type Pid = winapi::DWORD;
 pub type Handle = winapi::HANDLE;
 pub type Address = u32;
 
+/// A module (executable or DLL) loaded into a remote process
+pub struct Module {
+    pub name: String,
+    pub base: Address,
+    pub size: u32,
+}
+
+/// Access to the memory of a remote process
+///
+/// A `Handle` gives direct access. Features are handed a `&mut dyn ProcessMemory` instead, which
+/// `Read` and `Write` accept just the same, so they never need to know how the memory is reached.
+pub trait ProcessMemory {
+    fn read_bytes(&mut self, addr: Address, size: usize) -> Result<Vec<u8>, String>;
+    fn write_bytes(&mut self, addr: Address, data: &[u8]) -> Result<(), String>;
+    /// Write to memory that may be read-only, such as code
+    fn write_code(&mut self, addr: Address, data: &[u8]) -> Result<(), String>;
+    /// Allocate executable memory, returning its address
+    fn alloc(&mut self, len: usize) -> Result<Address, String>;
+}
+
+impl ProcessMemory for Handle {
+    fn read_bytes(&mut self, addr: Address, size: usize) -> Result<Vec<u8>, String> {
+        read(*self, addr, size)
+    }
+
+    fn write_bytes(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        write(*self, addr, data)
+    }
+
+    fn write_code(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        write_protected(*self, addr, data)
+    }
+
+    fn alloc(&mut self, len: usize) -> Result<Address, String> {
+        alloc_ex(*self, len)
+    }
+}
+
+impl<M: ProcessMemory + ?Sized> ProcessMemory for &mut M {
+    fn read_bytes(&mut self, addr: Address, size: usize) -> Result<Vec<u8>, String> {
+        (**self).read_bytes(addr, size)
+    }
+
+    fn write_bytes(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        (**self).write_bytes(addr, data)
+    }
+
+    fn write_code(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        (**self).write_code(addr, data)
+    }
+
+    fn alloc(&mut self, len: usize) -> Result<Address, String> {
+        (**self).alloc(len)
+    }
+}
+
 /// Read a type from the memory of a remote process
 pub trait Read {
-    fn read(handle: Handle, addr: Address) -> Result<Self, String>
+    fn read<M: ProcessMemory>(memory: M, addr: Address) -> Result<Self, String>
     where
         Self: Sized;
 }
 
 impl Read for u32 {
-    fn read(handle: Handle, addr: Address) -> Result<u32, String> {
-        let raw = read(handle, addr, std::mem::size_of::<u32>())?;
+    fn read<M: ProcessMemory>(mut memory: M, addr: Address) -> Result<u32, String> {
+        let raw = memory.read_bytes(addr, std::mem::size_of::<u32>())?;
         unsafe { Ok(*(raw.as_ptr() as *const u32)) }
     }
 }
 
 impl Read for i32 {
-    fn read(handle: Handle, addr: Address) -> Result<i32, String> {
-        let raw = read(handle, addr, std::mem::size_of::<i32>())?;
+    fn read<M: ProcessMemory>(mut memory: M, addr: Address) -> Result<i32, String> {
+        let raw = memory.read_bytes(addr, std::mem::size_of::<i32>())?;
         unsafe { Ok(*(raw.as_ptr() as *const i32)) }
     }
 }
 
 impl Read for f32 {
-    fn read(handle: Handle, addr: Address) -> Result<f32, String> {
-        let raw = read(handle, addr, std::mem::size_of::<f32>())?;
+    fn read<M: ProcessMemory>(mut memory: M, addr: Address) -> Result<f32, String> {
+        let raw = memory.read_bytes(addr, std::mem::size_of::<f32>())?;
         unsafe { Ok(*(raw.as_ptr() as *const f32)) }
     }
 }
 
+impl Read for bool {
+    fn read<M: ProcessMemory>(mut memory: M, addr: Address) -> Result<bool, String> {
+        let raw = memory.read_bytes(addr, std::mem::size_of::<bool>())?;
+        raw.first()
+            .map(|&b| b != 0)
+            .ok_or_else(|| String::from("short read of bool"))
+    }
+}
+
 /// Write a type to the memory of a remote process
 pub trait Write {
-    fn write(&self, handle: Handle, addr: Address) -> Result<(), String>;
+    fn write<M: ProcessMemory>(&self, memory: M, addr: Address) -> Result<(), String>;
 }
 
 impl Write for f32 {
-    fn write(&self, handle: Handle, addr: Address) -> Result<(), String> {
+    fn write<M: ProcessMemory>(&self, mut memory: M, addr: Address) -> Result<(), String> {
         let raw: [u8; 4] = unsafe { std::mem::transmute(*self) };
-        write(handle, addr, &raw[..])
+        memory.write_bytes(addr, &raw[..])
+    }
+}
+
+impl Write for bool {
+    fn write<M: ProcessMemory>(&self, mut memory: M, addr: Address) -> Result<(), String> {
+        memory.write_bytes(addr, &[*self as u8])
     }
 }
 
@@ -56,25 +132,69 @@ pub fn find(name: &str) -> Option<Pid> {
 
         let mut ok = winapi::Process32First(handle, &mut proc);
 
-        loop {
+        // This runs every second while waiting for the game, so the snapshot has to be closed
+        // rather than leaked, and an odd error is better reported than allowed to bring us down.
+        let result = loop {
             if ok == 0 {
                 let err = winapi_error::last();
-                if err.number == winapi::ERROR_NO_MORE_FILES {
-                    return None;
+                if err.number != winapi::ERROR_NO_MORE_FILES {
+                    eprintln!("unexpected Process32(First|Next) error: {}", err);
                 }
-                panic!(
-                    "unexpected Process32(First|Next) error: {}",
-                    err.to_string()
-                );
+                break None;
             }
 
             let exe = std::ffi::CStr::from_ptr(&proc.szExeFile as *const winapi::CHAR);
-            if exe.to_str().unwrap() == name {
-                return Some(proc.th32ProcessID);
+            if exe.to_string_lossy() == name {
+                break Some(proc.th32ProcessID);
             }
 
             ok = winapi::Process32Next(handle, &mut proc);
+        };
+
+        winapi::CloseHandle(handle);
+        result
+    }
+}
+
+/// Find the module having the given name in the process identified by the given PID
+pub fn module(pid: Pid, name: &str) -> Result<Module, String> {
+    unsafe {
+        let handle = winapi::CreateToolhelp32Snapshot(
+            winapi::TH32CS_SNAPMODULE | winapi::TH32CS_SNAPMODULE32,
+            pid,
+        );
+        if handle == winapi::INVALID_HANDLE_VALUE {
+            let err = winapi_error::last();
+            return Err(format!("CreateToolhelp32Snapshot error: {}", err));
         }
+        let mut entry: winapi::MODULEENTRY32 = std::mem::zeroed();
+        entry.dwSize = std::mem::size_of::<winapi::MODULEENTRY32>() as u32;
+
+        let mut ok = winapi::Module32First(handle, &mut entry);
+
+        let result = loop {
+            if ok == 0 {
+                let err = winapi_error::last();
+                if err.number == winapi::ERROR_NO_MORE_FILES {
+                    break Err(format!("module {} not found", name));
+                }
+                break Err(format!("Module32(First|Next) error: {}", err.to_string()));
+            }
+
+            let module = std::ffi::CStr::from_ptr(&entry.szModule as *const winapi::CHAR);
+            if module.to_string_lossy().eq_ignore_ascii_case(name) {
+                break Ok(Module {
+                    name: module.to_string_lossy().into_owned(),
+                    base: entry.modBaseAddr as Address,
+                    size: entry.modBaseSize,
+                });
+            }
+
+            ok = winapi::Module32Next(handle, &mut entry);
+        };
+
+        winapi::CloseHandle(handle);
+        result
     }
 }
 
@@ -0,0 +1,62 @@
+//! Process memory that lives in the test itself, for exercising code that reads the game
+
+use std::collections::BTreeMap;
+
+use super::{Address, ProcessMemory};
+
+/// A sparse address space; reading anything that was never put there fails like an unmapped page
+pub struct FakeMemory {
+    bytes: BTreeMap<Address, u8>,
+    /// Where the next allocation goes
+    next_alloc: Address,
+}
+
+impl FakeMemory {
+    pub fn new() -> FakeMemory {
+        FakeMemory {
+            bytes: BTreeMap::new(),
+            next_alloc: 0x7000_0000,
+        }
+    }
+
+    pub fn put(&mut self, addr: Address, data: &[u8]) {
+        for (offset, &byte) in data.iter().enumerate() {
+            self.bytes.insert(addr + offset as Address, byte);
+        }
+    }
+
+    pub fn put_i32(&mut self, addr: Address, value: i32) {
+        self.put(addr, &value.to_le_bytes());
+    }
+}
+
+impl ProcessMemory for FakeMemory {
+    fn read_bytes(&mut self, addr: Address, size: usize) -> Result<Vec<u8>, String> {
+        (0..size)
+            .map(|offset| {
+                let at = addr.wrapping_add(offset as Address);
+                self.bytes
+                    .get(&at)
+                    .copied()
+                    .ok_or_else(|| format!("nothing at {:#x}", at))
+            })
+            .collect()
+    }
+
+    fn write_bytes(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        self.put(addr, data);
+        Ok(())
+    }
+
+    fn write_code(&mut self, addr: Address, data: &[u8]) -> Result<(), String> {
+        self.put(addr, data);
+        Ok(())
+    }
+
+    fn alloc(&mut self, len: usize) -> Result<Address, String> {
+        let addr = self.next_alloc;
+        self.put(addr, &vec![0; len]);
+        self.next_alloc += len as Address;
+        Ok(addr)
+    }
+}
@@ -0,0 +1,96 @@
+//! Byte patterns with wildcards, for finding code whose address we don't know
+//!
+//! Patterns are written as hex bytes separated by spaces, with `?` or `??` matching any byte:
+//! `29 7b ?? 8b c7`. Leaving out the bytes that encode addresses and offsets lets a pattern
+//! survive the game being rebuilt.
+
+use crate::proc;
+use crate::proc::pe::{Image, Remote, Source};
+
+pub struct Pattern(Vec<Option<u8>>);
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Pattern, String> {
+        let bytes = text
+            .split_whitespace()
+            .map(|byte| match byte {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(byte, 16)
+                    .map(Some)
+                    .map_err(|_| format!("invalid byte in pattern: {}", byte)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if bytes.iter().all(Option::is_none) {
+            return Err(String::from(
+                "pattern needs at least one byte that isn't a wildcard",
+            ));
+        }
+
+        Ok(Pattern(bytes))
+    }
+
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.0.len()
+            && self
+                .0
+                .iter()
+                .zip(data)
+                .all(|(expected, &actual)| match expected {
+                    Some(b) => *b == actual,
+                    None => true,
+                })
+    }
+
+    /// The offsets in `data` at which the pattern matches
+    pub fn find_all(&self, data: &[u8]) -> Vec<usize> {
+        if data.len() < self.0.len() {
+            return Vec::new();
+        }
+        (0..=data.len() - self.0.len())
+            .filter(|&offset| self.matches(&data[offset..]))
+            .collect()
+    }
+}
+
+/// Find every match of `pattern` in the executable sections of the module at `base`
+pub fn scan(
+    handle: proc::Handle,
+    base: proc::Address,
+    image: &Image,
+    pattern: &Pattern,
+) -> Result<Vec<proc::Address>, String> {
+    let remote = Remote { handle, base };
+    let mut found = Vec::new();
+
+    for section in image.sections.iter().filter(|s| s.executable()) {
+        let code = remote.read_rva(section.virtual_address, section.virtual_size as usize)?;
+        for offset in pattern.find_all(&code) {
+            found.push(base + section.virtual_address + offset as u32);
+        }
+    }
+
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_any_byte() {
+        let pattern = Pattern::parse("29 7b ?? 8b c7").unwrap();
+        let data = [0x00, 0x29, 0x7b, 0x04, 0x8b, 0xc7, 0x29, 0x7b];
+        assert_eq!(pattern.find_all(&data), [1]);
+        // A match cut off by the end of the data doesn't count.
+        assert_eq!(pattern.find_all(&data[..5]), []);
+        assert_eq!(Pattern::parse("29 ? 8b").unwrap().find_all(&data), []);
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(Pattern::parse("29 zz").is_err());
+        assert!(Pattern::parse("?? ?").is_err());
+        assert!(Pattern::parse("").is_err());
+    }
+}
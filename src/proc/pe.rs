@@ -0,0 +1,614 @@
+//! Parsing of Portable Executable images
+//!
+//! The same parser works on a module mapped into a remote process and on a PE file read from disk.
+//! The only difference between the two is how a relative virtual address (RVA) is turned into
+//! bytes, which is what the `Source` trait abstracts over.
+
+use crate::proc;
+
+const DOS_MAGIC: u16 = 0x5a4d; // "MZ"
+const NT_SIGNATURE: u32 = 0x0000_4550; // "PE\0\0"
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+const FILE_HEADER_SIZE: u32 = 20;
+const SECTION_HEADER_SIZE: u32 = 40;
+const IMPORT_DESCRIPTOR_SIZE: u32 = 20;
+
+const DIRECTORY_EXPORT: u32 = 0;
+const DIRECTORY_IMPORT: u32 = 1;
+
+// Don't trust header counts blindly; a garbage header shouldn't make us read forever.
+const MAX_SECTIONS: u16 = 96;
+const MAX_IMPORTS: usize = 4096;
+const MAX_NAME_LEN: usize = 512;
+
+/// Somewhere the bytes of a PE image can be read from
+pub trait Source {
+    /// Read up to `len` bytes starting at the given relative virtual address
+    fn read_rva(&self, rva: u32, len: usize) -> Result<Vec<u8>, String>;
+}
+
+/// An image mapped into the memory of a remote process
+pub struct Remote {
+    pub handle: proc::Handle,
+    pub base: proc::Address,
+}
+
+impl Source for Remote {
+    fn read_rva(&self, rva: u32, len: usize) -> Result<Vec<u8>, String> {
+        proc::read(self.handle, add(self.base, rva)?, len)
+    }
+}
+
+/// An image read from a file on disk
+///
+/// On disk, sections are stored at their file offsets rather than at their virtual addresses, so
+/// RVAs are translated through the section table before reading.
+pub struct File {
+    data: Vec<u8>,
+    sections: Vec<Section>,
+}
+
+impl File {
+    pub fn new(data: Vec<u8>) -> Result<File, String> {
+        // Headers live at the same offset on disk and in memory, so an empty section table is
+        // enough to parse them.
+        let mut file = File {
+            data,
+            sections: Vec::new(),
+        };
+        file.sections = Headers::parse(&file)?.sections;
+        Ok(file)
+    }
+
+    fn offset(&self, rva: u32) -> Option<usize> {
+        for section in &self.sections {
+            let size = section.virtual_size.max(section.raw_size);
+            if rva >= section.virtual_address && rva - section.virtual_address < size {
+                let delta = rva - section.virtual_address;
+                if delta >= section.raw_size {
+                    // Uninitialized data (e.g. .bss) has no bytes on disk.
+                    return None;
+                }
+                return section
+                    .raw_offset
+                    .checked_add(delta)
+                    .map(|offset| offset as usize);
+            }
+        }
+
+        // Anything below the first section is part of the headers.
+        if self.sections.iter().all(|s| rva < s.virtual_address) {
+            Some(rva as usize)
+        } else {
+            None
+        }
+    }
+}
+
+impl Source for File {
+    fn read_rva(&self, rva: u32, len: usize) -> Result<Vec<u8>, String> {
+        let start = self
+            .offset(rva)
+            .filter(|&start| start < self.data.len())
+            .ok_or_else(|| format!("rva {:#x} is not backed by file data", rva))?;
+        let end = start.saturating_add(len).min(self.data.len());
+        Ok(self.data[start..end].to_vec())
+    }
+}
+
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub raw_offset: u32,
+    pub raw_size: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    pub const EXECUTE: u32 = 0x2000_0000;
+    pub const READ: u32 = 0x4000_0000;
+    pub const WRITE: u32 = 0x8000_0000;
+
+    pub fn executable(&self) -> bool {
+        self.characteristics & Section::EXECUTE != 0
+    }
+}
+
+pub struct Export {
+    /// Exports may be by ordinal only, in which case they have no name
+    pub name: Option<String>,
+    pub ordinal: u32,
+    pub rva: u32,
+    /// Set when the export is forwarded to another DLL, e.g. "NTDLL.RtlAllocateHeap"
+    pub forwarder: Option<String>,
+}
+
+pub struct Import {
+    pub dll: String,
+    pub functions: Vec<ImportedFunction>,
+}
+
+pub struct ImportedFunction {
+    /// Functions imported by ordinal have no name
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    /// Where the loader writes the resolved address of this function
+    pub iat_rva: u32,
+}
+
+/// A parsed PE image
+pub struct Image {
+    pub machine: u16,
+    pub timestamp: u32,
+    pub characteristics: u16,
+    pub pe32_plus: bool,
+    pub image_base: u64,
+    pub entry_point: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub sections: Vec<Section>,
+    pub exports: Vec<Export>,
+    pub imports: Vec<Import>,
+}
+
+impl Image {
+    /// Parse the image of a module loaded at `base` in a remote process
+    pub fn read_remote(handle: proc::Handle, base: proc::Address) -> Result<Image, String> {
+        Image::parse(&Remote { handle, base })
+    }
+
+    /// Parse a PE file on disk
+    pub fn read_file<P: AsRef<std::path::Path>>(path: P) -> Result<Image, String> {
+        let data = std::fs::read(path).map_err(|err| format!("failed to read file: {}", err))?;
+        Image::parse(&File::new(data)?)
+    }
+
+    pub fn parse(src: &dyn Source) -> Result<Image, String> {
+        let headers = Headers::parse(src)?;
+
+        let exports = match headers.directories.get(DIRECTORY_EXPORT as usize) {
+            Some(dir) if dir.rva != 0 => parse_exports(src, dir)?,
+            _ => Vec::new(),
+        };
+
+        let imports = match headers.directories.get(DIRECTORY_IMPORT as usize) {
+            Some(dir) if dir.rva != 0 => parse_imports(src, dir, headers.pe32_plus)?,
+            _ => Vec::new(),
+        };
+
+        Ok(Image {
+            machine: headers.machine,
+            timestamp: headers.timestamp,
+            characteristics: headers.characteristics,
+            pe32_plus: headers.pe32_plus,
+            image_base: headers.image_base,
+            entry_point: headers.entry_point,
+            size_of_image: headers.size_of_image,
+            size_of_headers: headers.size_of_headers,
+            sections: headers.sections,
+            exports,
+            imports,
+        })
+    }
+}
+
+struct Directory {
+    rva: u32,
+    size: u32,
+}
+
+struct Headers {
+    machine: u16,
+    timestamp: u32,
+    characteristics: u16,
+    pe32_plus: bool,
+    image_base: u64,
+    entry_point: u32,
+    size_of_image: u32,
+    size_of_headers: u32,
+    directories: Vec<Directory>,
+    sections: Vec<Section>,
+}
+
+impl Headers {
+    fn parse(src: &dyn Source) -> Result<Headers, String> {
+        if read_u16(src, 0)? != DOS_MAGIC {
+            return Err(String::from("missing MZ signature"));
+        }
+
+        let nt = read_u32(src, 0x3c)?;
+        if read_u32(src, nt)? != NT_SIGNATURE {
+            return Err(String::from("missing PE signature"));
+        }
+
+        let file_header = add(nt, 4)?;
+        let machine = read_u16(src, file_header)?;
+        let section_count = read_u16(src, add(file_header, 2)?)?;
+        let timestamp = read_u32(src, add(file_header, 4)?)?;
+        let optional_size = read_u16(src, add(file_header, 16)?)?;
+        let characteristics = read_u16(src, add(file_header, 18)?)?;
+
+        if section_count > MAX_SECTIONS {
+            return Err(format!("implausible section count: {}", section_count));
+        }
+
+        let optional = add(file_header, FILE_HEADER_SIZE)?;
+        let pe32_plus = match read_u16(src, optional)? {
+            PE32_MAGIC => false,
+            PE32_PLUS_MAGIC => true,
+            magic => return Err(format!("unknown optional header magic: {:#x}", magic)),
+        };
+
+        let entry_point = read_u32(src, add(optional, 16)?)?;
+        let image_base = if pe32_plus {
+            read_u64(src, add(optional, 24)?)?
+        } else {
+            read_u32(src, add(optional, 28)?)? as u64
+        };
+        let size_of_image = read_u32(src, add(optional, 56)?)?;
+        let size_of_headers = read_u32(src, add(optional, 60)?)?;
+
+        let (count_offset, directories_offset) = if pe32_plus { (108, 112) } else { (92, 96) };
+        let directory_count = read_u32(src, add(optional, count_offset)?)?.min(16);
+        let directories_start = add(optional, directories_offset)?;
+        let mut directories = Vec::with_capacity(directory_count as usize);
+        for index in 0..directory_count {
+            let entry = element(directories_start, index, 8)?;
+            directories.push(Directory {
+                rva: read_u32(src, entry)?,
+                size: read_u32(src, add(entry, 4)?)?,
+            });
+        }
+
+        let section_table = add(optional, optional_size as u32)?;
+        let mut sections = Vec::with_capacity(section_count as usize);
+        for index in 0..section_count as u32 {
+            let header = element(section_table, index, SECTION_HEADER_SIZE)?;
+            let raw_name = read_exact(src, header, 8)?;
+            let name = raw_name
+                .iter()
+                .take_while(|&c| *c != 0)
+                .map(|&c| c as char)
+                .collect();
+
+            sections.push(Section {
+                name,
+                virtual_size: read_u32(src, add(header, 8)?)?,
+                virtual_address: read_u32(src, add(header, 12)?)?,
+                raw_size: read_u32(src, add(header, 16)?)?,
+                raw_offset: read_u32(src, add(header, 20)?)?,
+                characteristics: read_u32(src, add(header, 36)?)?,
+            });
+        }
+
+        Ok(Headers {
+            machine,
+            timestamp,
+            characteristics,
+            pe32_plus,
+            image_base,
+            entry_point,
+            size_of_image,
+            size_of_headers,
+            directories,
+            sections,
+        })
+    }
+}
+
+fn parse_exports(src: &dyn Source, dir: &Directory) -> Result<Vec<Export>, String> {
+    let ordinal_base = read_u32(src, add(dir.rva, 16)?)?;
+    let function_count = read_u32(src, add(dir.rva, 20)?)?;
+    let name_count = read_u32(src, add(dir.rva, 24)?)?;
+    let functions = read_u32(src, add(dir.rva, 28)?)?;
+    let names = read_u32(src, add(dir.rva, 32)?)?;
+    let name_ordinals = read_u32(src, add(dir.rva, 36)?)?;
+
+    if function_count as usize > MAX_IMPORTS * 16 || name_count > function_count {
+        return Err(format!(
+            "implausible export counts: {} functions, {} names",
+            function_count, name_count
+        ));
+    }
+
+    let mut exports = Vec::with_capacity(function_count as usize);
+    for index in 0..function_count {
+        let rva = read_u32(src, element(functions, index, 4)?)?;
+        if rva == 0 {
+            continue;
+        }
+
+        // A function RVA pointing back into the export directory is a forwarder string.
+        let forwarder = if rva >= dir.rva && rva - dir.rva < dir.size {
+            Some(read_cstr(src, rva)?)
+        } else {
+            None
+        };
+
+        exports.push(Export {
+            name: None,
+            ordinal: add(ordinal_base, index)?,
+            rva,
+            forwarder,
+        });
+    }
+
+    for index in 0..name_count {
+        let name_rva = read_u32(src, element(names, index, 4)?)?;
+        let ordinal = add(
+            ordinal_base,
+            read_u16(src, element(name_ordinals, index, 2)?)? as u32,
+        )?;
+        let name = read_cstr(src, name_rva)?;
+        if let Some(export) = exports.iter_mut().find(|e| e.ordinal == ordinal) {
+            export.name = Some(name);
+        }
+    }
+
+    Ok(exports)
+}
+
+fn parse_imports(
+    src: &dyn Source,
+    dir: &Directory,
+    pe32_plus: bool,
+) -> Result<Vec<Import>, String> {
+    let thunk_size = if pe32_plus { 8 } else { 4 };
+    let mut imports = Vec::new();
+
+    for index in 0..MAX_IMPORTS as u32 {
+        let descriptor = element(dir.rva, index, IMPORT_DESCRIPTOR_SIZE)?;
+        let original_first_thunk = read_u32(src, descriptor)?;
+        let name_rva = read_u32(src, add(descriptor, 12)?)?;
+        let first_thunk = read_u32(src, add(descriptor, 16)?)?;
+
+        if name_rva == 0 && first_thunk == 0 {
+            break;
+        }
+
+        // Once loaded, the first thunk holds resolved addresses rather than names, so prefer the
+        // original (lookup) thunk when the linker emitted one.
+        let lookup = if original_first_thunk != 0 {
+            original_first_thunk
+        } else {
+            first_thunk
+        };
+
+        let mut functions = Vec::new();
+        for slot in 0..MAX_IMPORTS as u32 {
+            let thunk_rva = element(lookup, slot, thunk_size)?;
+            let (thunk, by_ordinal) = if pe32_plus {
+                let thunk = read_u64(src, thunk_rva)?;
+                (thunk, thunk & (1 << 63) != 0)
+            } else {
+                let thunk = read_u32(src, thunk_rva)? as u64;
+                (thunk, thunk & (1 << 31) != 0)
+            };
+
+            if thunk == 0 {
+                break;
+            }
+
+            let iat_rva = element(first_thunk, slot, thunk_size)?;
+            functions.push(if by_ordinal {
+                ImportedFunction {
+                    name: None,
+                    ordinal: Some(thunk as u16),
+                    iat_rva,
+                }
+            } else {
+                // Skip the two byte hint preceding the name.
+                ImportedFunction {
+                    name: Some(read_cstr(src, add(thunk as u32, 2)?)?),
+                    ordinal: None,
+                    iat_rva,
+                }
+            });
+        }
+
+        imports.push(Import {
+            dll: read_cstr(src, name_rva)?,
+            functions,
+        });
+    }
+
+    Ok(imports)
+}
+
+/// Add an offset to an RVA taken from the image, which may be garbage
+fn add(rva: u32, offset: u32) -> Result<u32, String> {
+    rva.checked_add(offset)
+        .ok_or_else(|| format!("rva {:#x} + {:#x} is out of range", rva, offset))
+}
+
+/// The RVA of an element of a table in the image
+fn element(table: u32, index: u32, size: u32) -> Result<u32, String> {
+    index
+        .checked_mul(size)
+        .ok_or_else(|| {
+            format!(
+                "element {} of the table at rva {:#x} is out of range",
+                index, table
+            )
+        })
+        .and_then(|offset| add(table, offset))
+}
+
+fn read_exact(src: &dyn Source, rva: u32, len: usize) -> Result<Vec<u8>, String> {
+    let data = src.read_rva(rva, len)?;
+    if data.len() != len {
+        return Err(format!("short read of {} bytes at rva {:#x}", len, rva));
+    }
+    Ok(data)
+}
+
+fn read_u16(src: &dyn Source, rva: u32) -> Result<u16, String> {
+    let data = read_exact(src, rva, 2)?;
+    Ok(u16::from_le_bytes([data[0], data[1]]))
+}
+
+fn read_u32(src: &dyn Source, rva: u32) -> Result<u32, String> {
+    let data = read_exact(src, rva, 4)?;
+    Ok(u32::from_le_bytes([data[0], data[1], data[2], data[3]]))
+}
+
+fn read_u64(src: &dyn Source, rva: u32) -> Result<u64, String> {
+    let data = read_exact(src, rva, 8)?;
+    let mut raw = [0; 8];
+    raw.copy_from_slice(&data);
+    Ok(u64::from_le_bytes(raw))
+}
+
+/// Read a NUL-terminated ASCII string
+fn read_cstr(src: &dyn Source, rva: u32) -> Result<String, String> {
+    let mut name = Vec::new();
+
+    // Read in small chunks that never straddle a page, so that a string ending right before an
+    // unmapped page in a remote process can still be read.
+    while name.len() < MAX_NAME_LEN {
+        let at = add(rva, name.len() as u32)?;
+        let chunk_len = (0x1000 - (at % 0x1000)).min(64) as usize;
+        let chunk = src.read_rva(at, chunk_len)?;
+        if chunk.is_empty() {
+            break;
+        }
+        match chunk.iter().position(|&c| c == 0) {
+            Some(end) => {
+                name.extend_from_slice(&chunk[..end]);
+                return Ok(String::from_utf8_lossy(&name).into_owned());
+            }
+            None => name.extend_from_slice(&chunk),
+        }
+    }
+
+    Err(format!("unterminated string at rva {:#x}", rva))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTION_RVA: u32 = 0x1000;
+    const SECTION_OFFSET: u32 = 0x200;
+
+    /// A PE32 file with one section holding an export of `Exported` and an import of
+    /// `KERNEL32.dll!ExitProcess`
+    fn build() -> Vec<u8> {
+        let mut data = vec![0; 0x400];
+        let mut put = |offset: u32, bytes: &[u8]| {
+            let offset = offset as usize;
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+        let file = |rva: u32| rva - SECTION_RVA + SECTION_OFFSET;
+
+        put(0, b"MZ");
+        put(0x3c, &0x40u32.to_le_bytes());
+        put(0x40, b"PE\0\0");
+        put(0x44, &0x14cu16.to_le_bytes());
+        put(0x46, &1u16.to_le_bytes());
+        put(0x48, &0x5f00_0000u32.to_le_bytes());
+        put(0x54, &0xe0u16.to_le_bytes());
+        put(0x56, &0x2102u16.to_le_bytes());
+
+        let optional = 0x58;
+        put(optional, &PE32_MAGIC.to_le_bytes());
+        put(optional + 16, &0x1234u32.to_le_bytes());
+        put(optional + 28, &0x0040_0000u32.to_le_bytes());
+        put(optional + 56, &0x2000u32.to_le_bytes());
+        put(optional + 60, &0x200u32.to_le_bytes());
+        put(optional + 92, &16u32.to_le_bytes());
+        // Exports at 0x1000, imports at 0x1080
+        put(optional + 96, &0x1000u32.to_le_bytes());
+        put(optional + 100, &0x60u32.to_le_bytes());
+        put(optional + 104, &0x1080u32.to_le_bytes());
+        put(optional + 108, &0x28u32.to_le_bytes());
+
+        let section = optional + 0xe0;
+        put(section, b".rdata\0\0");
+        put(section + 8, &0x200u32.to_le_bytes());
+        put(section + 12, &SECTION_RVA.to_le_bytes());
+        put(section + 16, &0x200u32.to_le_bytes());
+        put(section + 20, &SECTION_OFFSET.to_le_bytes());
+        put(section + 36, &0x4000_0040u32.to_le_bytes());
+
+        put(file(0x1000 + 16), &1u32.to_le_bytes());
+        put(file(0x1000 + 20), &1u32.to_le_bytes());
+        put(file(0x1000 + 24), &1u32.to_le_bytes());
+        put(file(0x1000 + 28), &0x1040u32.to_le_bytes());
+        put(file(0x1000 + 32), &0x1044u32.to_le_bytes());
+        put(file(0x1000 + 36), &0x1048u32.to_le_bytes());
+        put(file(0x1040), &0x1234u32.to_le_bytes());
+        put(file(0x1044), &0x1050u32.to_le_bytes());
+        put(file(0x1048), &0u16.to_le_bytes());
+        put(file(0x1050), b"Exported\0");
+
+        put(file(0x1080), &0x10c0u32.to_le_bytes());
+        put(file(0x1080 + 12), &0x10d0u32.to_le_bytes());
+        put(file(0x1080 + 16), &0x10e0u32.to_le_bytes());
+        put(file(0x10c0), &0x1100u32.to_le_bytes());
+        put(file(0x10d0), b"KERNEL32.dll\0");
+        put(file(0x10e0), &0x1100u32.to_le_bytes());
+        put(file(0x1100 + 2), b"ExitProcess\0");
+
+        data
+    }
+
+    #[test]
+    fn parses_headers_and_sections() {
+        let image = Image::parse(&File::new(build()).unwrap()).unwrap();
+        assert_eq!(image.machine, 0x14c);
+        assert!(!image.pe32_plus);
+        assert_eq!(image.image_base, 0x0040_0000);
+        assert_eq!(image.entry_point, 0x1234);
+        assert_eq!(image.size_of_image, 0x2000);
+        assert_eq!(image.sections.len(), 1);
+        assert_eq!(image.sections[0].name, ".rdata");
+        assert!(!image.sections[0].executable());
+    }
+
+    #[test]
+    fn parses_exports() {
+        let image = Image::parse(&File::new(build()).unwrap()).unwrap();
+        assert_eq!(image.exports.len(), 1);
+        let export = &image.exports[0];
+        assert_eq!(export.name.as_deref(), Some("Exported"));
+        assert_eq!(export.ordinal, 1);
+        assert_eq!(export.rva, 0x1234);
+        assert!(export.forwarder.is_none());
+    }
+
+    #[test]
+    fn parses_imports() {
+        let image = Image::parse(&File::new(build()).unwrap()).unwrap();
+        assert_eq!(image.imports.len(), 1);
+        let import = &image.imports[0];
+        assert_eq!(import.dll, "KERNEL32.dll");
+        assert_eq!(import.functions.len(), 1);
+        assert_eq!(import.functions[0].name.as_deref(), Some("ExitProcess"));
+        assert_eq!(import.functions[0].ordinal, None);
+        assert_eq!(import.functions[0].iat_rva, 0x10e0);
+    }
+
+    #[test]
+    fn rejects_out_of_range_offsets() {
+        // A second section at the very top of the address space, holding the export table's
+        // function addresses
+        let mut data = build();
+        let put = |data: &mut Vec<u8>, offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        data[0x46] = 2;
+        data[0x160..0x168].copy_from_slice(b".top\0\0\0\0");
+        put(&mut data, 0x160 + 8, 0x2000);
+        put(&mut data, 0x160 + 12, 0xffff_f000);
+        put(&mut data, 0x160 + 16, 0x200);
+        put(&mut data, 0x160 + 20, SECTION_OFFSET);
+        let functions = (0x1000 + 28 - SECTION_RVA + SECTION_OFFSET) as usize;
+        put(&mut data, functions, 0xffff_fffe);
+
+        assert!(Image::parse(&File::new(data).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+//! Running every feature from one loop over one view of the game
+//!
+//! Each tick, the game state the features share is read once into a `WorldSnapshot`, and every
+//! feature that's due is handed that snapshot. Features decide for themselves how long to wait
+//! until their next tick, so each can run at its own rate, and the loop sleeps until the next one
+//! is due.
+//!
+//! Reads and writes can fail at any time, since the game keeps changing underneath us. Failures
+//! come in three kinds:
+//!
+//! * Entity-level: a single player vanishing mid-read, e.g. because they left. Only that entity
+//!   is skipped; see `entities::player_slots`.
+//! * Transient: anything else that goes wrong while the game is still running, such as a read
+//!   racing a map change. The snapshot or feature is tried again shortly, and the error is
+//!   reported once rather than on every retry. The map is the exception: features can do without
+//!   it for a while, so the snapshot goes ahead without one and the failure is only reported.
+//! * Fatal: losing track of the process itself. The scheduler detaches and returns the error, so
+//!   the caller can wait for the game and attach again.
+//!
+//! Ctrl+C stops the scheduler rather than the process, once `catch_interrupts` is called, so that
+//! features still get detached: the dashboard hands the console back, patches are taken out of
+//! the game and a held attack button is let go.
+
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::entities::{self, Item, Player};
+use crate::feature::Feature;
+use crate::game::GameMode;
+use crate::proc::{self, ProcessMemory, Read};
+use crate::winapi;
+use crate::winapi_error;
+use crate::world::{self, World};
+
+/// How long to wait between checks when there are no features to run
+const IDLE_WAIT: Duration = Duration::from_millis(100);
+
+/// How long to wait before trying again after a transient failure
+const RETRY_WAIT: Duration = Duration::from_millis(100);
+
+/// What the scheduler runs features against: the game's memory, and whether it's still running
+pub trait Game: ProcessMemory {
+    /// Whether the game is still running; failing to find out means we've lost track of it
+    fn still_active(&mut self) -> Result<bool, String>;
+}
+
+impl Game for proc::Handle {
+    fn still_active(&mut self) -> Result<bool, String> {
+        proc::still_active(*self).map_err(|err| format!("lost track of the game: {}", err))
+    }
+}
+
+/// Set by the console's Ctrl+C handler, which runs on a thread of its own
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// The game state shared by every feature in a tick
+pub struct WorldSnapshot {
+    pub taken_at: Instant,
+    /// Address of the local player, for features that write to it
+    pub my_addr: proc::Address,
+    pub me: Player,
+    /// The player list by slot, as read by `entities::player_slots`
+    pub players: Vec<Option<Player>>,
+    pub mode: GameMode,
+    pub items: Vec<Item>,
+    /// The map geometry, unless it couldn't be read
+    pub world: Option<Rc<World>>,
+}
+
+impl WorldSnapshot {
+    /// Read the game state, looking the map up in `world`
+    ///
+    /// Failing to read the map leaves the snapshot without one rather than failing it; the error
+    /// is handed back alongside instead.
+    pub fn read<M: ProcessMemory>(
+        mut memory: M,
+        world: &mut world::Cache,
+    ) -> Result<(WorldSnapshot, Option<String>), String> {
+        let my_addr = proc::Address::read(&mut memory, 0x50f4f4)?;
+        let me = Player::read(&mut memory, my_addr)?;
+        let players = entities::player_slots(&mut memory)?;
+        let mode = GameMode::read(&mut memory)?;
+        // Both the items and the map's fingerprint come from the entity list.
+        let entity_list = entities::entity_list(&mut memory)?;
+        let (world, world_error) = match world.get(&mut memory, &entity_list) {
+            Ok(world) => (Some(world), None),
+            Err(err) => (None, Some(err)),
+        };
+
+        let snapshot = WorldSnapshot {
+            taken_at: Instant::now(),
+            my_addr,
+            me,
+            players,
+            mode,
+            items: entities::item_list(&entity_list),
+            world,
+        };
+        Ok((snapshot, world_error))
+    }
+
+    /// The players other than us, without the empty slots
+    pub fn players(&self) -> impl Iterator<Item = &Player> {
+        self.players.iter().flatten()
+    }
+}
+
+struct Scheduled {
+    feature: Box<dyn Feature>,
+    due: Instant,
+    /// Whether the last tick failed, so that a failure is only reported when it starts
+    failing: bool,
+}
+
+pub struct Scheduler {
+    features: Vec<Scheduled>,
+    /// The map is cached per game, since a new process has it at a new address.
+    world: world::Cache,
+    snapshot_failing: bool,
+    world_failing: bool,
+}
+
+impl Scheduler {
+    pub fn new(features: Vec<Box<dyn Feature>>) -> Scheduler {
+        let now = Instant::now();
+        Scheduler {
+            features: features
+                .into_iter()
+                .map(|feature| Scheduled {
+                    feature,
+                    due: now,
+                    failing: false,
+                })
+                .collect(),
+            world: world::Cache::new(),
+            snapshot_failing: false,
+            world_failing: false,
+        }
+    }
+
+    /// Attach the features to the game and run them until it exits or we're interrupted, then
+    /// detach them
+    ///
+    /// `reload` is checked every tick for a new configuration to hand to the features. An error
+    /// means the game couldn't be worked with any more, even though it may still be running.
+    pub fn run<G: Game>(
+        &mut self,
+        mut game: G,
+        reload: &mut dyn FnMut() -> Option<Config>,
+    ) -> Result<(), String> {
+        self.attach(&mut game, Instant::now());
+        let result = self.run_attached(&mut game, reload);
+        for scheduled in &mut self.features {
+            scheduled.feature.on_detach(&mut game);
+        }
+        result
+    }
+
+    fn attach<G: Game>(&mut self, game: &mut G, now: Instant) {
+        self.world = world::Cache::new();
+        self.snapshot_failing = false;
+        self.world_failing = false;
+        for scheduled in &mut self.features {
+            scheduled.feature.on_attach(&mut *game);
+            scheduled.due = now;
+            scheduled.failing = false;
+        }
+    }
+
+    fn run_attached<G: Game>(
+        &mut self,
+        game: &mut G,
+        reload: &mut dyn FnMut() -> Option<Config>,
+    ) -> Result<(), String> {
+        while !interrupted() && game.still_active()? {
+            if let Some(config) = reload() {
+                for scheduled in &mut self.features {
+                    scheduled.feature.configure(&config);
+                }
+            }
+
+            if !self.tick(game, Instant::now())? {
+                std::thread::sleep(RETRY_WAIT);
+                continue;
+            }
+
+            let wait = match self.features.iter().map(|s| s.due).min() {
+                Some(due) => due.saturating_duration_since(Instant::now()),
+                None => IDLE_WAIT,
+            };
+            std::thread::sleep(wait);
+        }
+
+        Ok(())
+    }
+
+    /// Run the features that are due at `now` on a fresh snapshot, returning false if it
+    /// couldn't be read
+    fn tick<G: Game>(&mut self, game: &mut G, now: Instant) -> Result<bool, String> {
+        if !self.features.iter().any(|s| s.due <= now) {
+            return Ok(true);
+        }
+
+        let result = WorldSnapshot::read(&mut *game, &mut self.world);
+        let (snapshot, world_error) =
+            match transient(game, &mut self.snapshot_failing, "reading", result)? {
+                Some(read) => read,
+                None => return Ok(false),
+            };
+        let world_result = match world_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        };
+        transient(
+            game,
+            &mut self.world_failing,
+            "reading the map",
+            world_result,
+        )?;
+
+        for scheduled in self.features.iter_mut().filter(|s| s.due <= now) {
+            let result = scheduled.feature.on_tick(&snapshot, &mut *game);
+            let name = scheduled.feature.name();
+            // Schedule from when the tick was due rather than when it finished, so that slow
+            // reads don't make features drift, but never into the past.
+            let next = match transient(game, &mut scheduled.failing, name, result)? {
+                Some(wait) => scheduled.due + wait,
+                None => now + RETRY_WAIT,
+            };
+            scheduled.due = next.max(now);
+        }
+
+        Ok(true)
+    }
+}
+
+/// Have Ctrl+C and Ctrl+Break stop the scheduler instead of killing the process
+pub fn catch_interrupts() -> Result<(), String> {
+    let ok = unsafe { winapi::SetConsoleCtrlHandler(Some(on_interrupt), 1) };
+    if ok == 0 {
+        let err = winapi_error::last();
+        return Err(format!("SetConsoleCtrlHandler error: {}", err));
+    }
+    Ok(())
+}
+
+/// Whether Ctrl+C or Ctrl+Break was pressed
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+unsafe extern "system" fn on_interrupt(ctrl_type: winapi::DWORD) -> winapi::BOOL {
+    // Returning false passes the event on to the default handler, which ends the process. That's
+    // left to closing the console, which can't be held off for long enough to detach, and to a
+    // second press, in case detaching hangs.
+    match ctrl_type {
+        winapi::CTRL_C_EVENT | winapi::CTRL_BREAK_EVENT
+            if !INTERRUPTED.swap(true, Ordering::SeqCst) =>
+        {
+            1
+        }
+        _ => 0,
+    }
+}
+
+/// Deal with the outcome of something that may fail transiently, returning its value if it worked
+///
+/// A failure is reported when it starts, unless it was down to the game exiting, which the
+/// scheduler notices by itself.
+fn transient<G: Game, T>(
+    game: &mut G,
+    failing: &mut bool,
+    what: &str,
+    result: Result<T, String>,
+) -> Result<Option<T>, String> {
+    match result {
+        Ok(value) => {
+            if *failing {
+                eprintln!("{} recovered", what);
+                *failing = false;
+            }
+            Ok(Some(value))
+        }
+        Err(err) => {
+            if !*failing && game.still_active()? {
+                eprintln!("{} failed, retrying: {}", what, err);
+            }
+            *failing = true;
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feature::Setting;
+    use crate::game::GAME_MODE_ADDR;
+    use crate::proc::fake::FakeMemory;
+    use std::cell::RefCell;
+
+    impl Game for FakeMemory {
+        fn still_active(&mut self) -> Result<bool, String> {
+            Ok(true)
+        }
+    }
+
+    /// Each feature's name as it ticks, and whether it was given a map
+    type Log = Rc<RefCell<Vec<(&'static str, bool)>>>;
+
+    /// Ticks every `rate`, or fails every time without one
+    struct Every {
+        name: &'static str,
+        rate: Option<Duration>,
+        log: Log,
+    }
+
+    impl Feature for Every {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn schema(&self) -> &'static [Setting] {
+            &[]
+        }
+
+        fn configure(&mut self, _config: &Config) {}
+
+        fn on_tick(
+            &mut self,
+            snapshot: &WorldSnapshot,
+            _memory: &mut dyn ProcessMemory,
+        ) -> Result<Duration, String> {
+            self.log
+                .borrow_mut()
+                .push((self.name, snapshot.world.is_some()));
+            self.rate.ok_or_else(|| String::from("broken"))
+        }
+    }
+
+    fn scheduler(features: &[(&'static str, Option<u64>)], log: &Log) -> Scheduler {
+        Scheduler::new(
+            features
+                .iter()
+                .map(|&(name, rate)| {
+                    Box::new(Every {
+                        name,
+                        rate: rate.map(Duration::from_millis),
+                        log: Rc::clone(log),
+                    }) as Box<dyn Feature>
+                })
+                .collect(),
+        )
+    }
+
+    /// Just us in a game without entities or a map
+    fn game() -> FakeMemory {
+        let mut memory = FakeMemory::new();
+        let me = memory.alloc(0x400).unwrap();
+        memory.put_i32(0x50f4f4, me as i32);
+        for &addr in &[0x50f4f8, 0x50f500, 0x50f4e8, 0x50f4f0, GAME_MODE_ADDR] {
+            memory.put_i32(addr, 0);
+        }
+        memory
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn features_tick_at_their_own_rates() {
+        let log = Log::default();
+        let mut scheduler = scheduler(
+            &[("fast", Some(10)), ("slow", Some(25)), ("broken", None)],
+            &log,
+        );
+        let mut game = game();
+        let start = Instant::now();
+        scheduler.attach(&mut game, start);
+
+        let mut ticked = Vec::new();
+        for step in 0..=20 {
+            assert_eq!(scheduler.tick(&mut game, start + ms(step * 5)), Ok(true));
+            for (name, _) in log.borrow_mut().drain(..) {
+                ticked.push((step * 5, name));
+            }
+        }
+
+        let at = |name| -> Vec<u64> {
+            ticked
+                .iter()
+                .filter(|&&(_, n)| n == name)
+                .map(|&(at, _)| at)
+                .collect()
+        };
+        assert_eq!(at("fast"), [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+        assert_eq!(at("slow"), [0, 25, 50, 75, 100]);
+        // A failing feature is retried after a pause rather than at once.
+        assert_eq!(at("broken"), [0, 100]);
+        // Features due at the same time run in order.
+        assert_eq!(&ticked[..3], [(0, "fast"), (0, "slow"), (0, "broken")]);
+    }
+
+    #[test]
+    fn a_missing_map_only_leaves_the_world_out() {
+        let log = Log::default();
+        let mut scheduler = scheduler(&[("feature", Some(10))], &log);
+        let mut game = game();
+        let start = Instant::now();
+        scheduler.attach(&mut game, start);
+
+        assert_eq!(scheduler.tick(&mut game, start), Ok(true));
+        assert_eq!(log.borrow_mut().split_off(0), [("feature", false)]);
+
+        let cells = game.alloc(16 * world::CELL_SIZE).unwrap();
+        game.put_i32(world::WORLD_ADDR, cells as i32);
+        game.put_i32(world::SFACTOR_ADDR, 2);
+        assert_eq!(scheduler.tick(&mut game, start + ms(10)), Ok(true));
+        assert_eq!(log.borrow_mut().split_off(0), [("feature", true)]);
+    }
+
+    #[test]
+    fn a_failed_snapshot_skips_the_tick() {
+        let log = Log::default();
+        let mut scheduler = scheduler(&[("feature", Some(10))], &log);
+        let mut game = game();
+        let start = Instant::now();
+        scheduler.attach(&mut game, start);
+
+        let me = proc::Address::read(&mut game, 0x50f4f4).unwrap();
+        game.put_i32(0x50f4f4, 0);
+        assert_eq!(scheduler.tick(&mut game, start), Ok(false));
+        assert!(log.borrow().is_empty());
+
+        // The feature is still due once the snapshot can be read again.
+        game.put_i32(0x50f4f4, me as i32);
+        assert_eq!(scheduler.tick(&mut game, start + ms(100)), Ok(true));
+        assert_eq!(log.borrow().len(), 1);
+    }
+}
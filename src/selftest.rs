@@ -0,0 +1,137 @@
+//! Checks that the trainer's offsets match the running game
+//!
+//! Each check reads through a group of offsets and makes sure what comes back is plausible, which
+//! is the quickest way to notice that a game update moved something. The trainer's own logic is
+//! covered by the unit tests instead.
+
+use crate::code;
+use crate::entities::{self, Player};
+use crate::game::GameMode;
+use crate::math;
+use crate::proc::{self, Read};
+use crate::weapons::Recoil;
+use crate::world;
+
+/// Run every check, printing the result of each, and return whether they all passed
+pub fn run(handle: proc::Handle) -> bool {
+    let mut passed = true;
+    let mut check = |name: &str, result: Result<(), String>| match result {
+        Ok(()) => println!("ok      {}", name),
+        Err(err) => {
+            println!("FAILED  {}: {}", name, err);
+            passed = false;
+        }
+    };
+
+    check("local player", local_player(handle));
+    check("player list", player_list(handle));
+    check("game mode", game_mode(handle));
+    check("weapon recoil", weapon_recoil(handle));
+    check("view projection", view_projection(handle));
+    check("world geometry", world_geometry(handle));
+    for &name in code::PATCHES {
+        check(&format!("{} patch site", name), patch_site(handle, name));
+    }
+
+    passed
+}
+
+fn plausible(player: &Player) -> Result<(), String> {
+    let p = player.position;
+    if !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite()) {
+        return Err(format!("{} is at {:?}", player.name, p));
+    }
+    if player.view.pitch.abs() > math::MAX_PITCH {
+        return Err(format!(
+            "{} has a pitch of {}",
+            player.name, player.view.pitch
+        ));
+    }
+    // The eyes sit straight above the feet, a little lower while crouching.
+    let eyes = player.position - player.feet;
+    if !(player.eye_height > 0.0 && player.eye_height < 10.0)
+        || eyes.x.abs() > 0.1
+        || eyes.y.abs() > 0.1
+        || (eyes.z - player.eye_height).abs() > 0.1
+    {
+        return Err(format!(
+            "{} has eyes {} above feet at {:?}, {:?} away",
+            player.name, player.eye_height, player.feet, eyes
+        ));
+    }
+    Ok(())
+}
+
+fn local_player(handle: proc::Handle) -> Result<(), String> {
+    let addr = proc::Address::read(handle, 0x50f4f4)?;
+    if addr == 0 {
+        return Err(String::from("player pointer is null"));
+    }
+    plausible(&Player::read(handle, addr)?)
+}
+
+fn player_list(handle: proc::Handle) -> Result<(), String> {
+    let players = entities::player_slots(handle)?;
+    players.iter().flatten().try_for_each(plausible)
+}
+
+fn game_mode(handle: proc::Handle) -> Result<(), String> {
+    let GameMode(mode) = GameMode::read(handle)?;
+    // -1 is used before the first game has started.
+    if !(-1..=22).contains(&mode) {
+        return Err(format!("mode {}", mode));
+    }
+    Ok(())
+}
+
+/// Read the held gun's recoil, which no gun has negative or kicking past its own maximum
+fn weapon_recoil(handle: proc::Handle) -> Result<(), String> {
+    let recoil = Recoil::read(handle, proc::Address::read(handle, 0x50f4f4)?)?;
+    if recoil.base < 0 || recoil.increase < 0 || recoil.base > recoil.max || recoil.max > 900 {
+        return Err(format!("{:?}", recoil));
+    }
+    Ok(())
+}
+
+/// Project what the local player is looking at, which should land on the crosshair
+///
+/// The matrix is from the last rendered frame, so a player turning while this runs may be a
+/// little off; anything more means the matrix or the screen size moved.
+fn view_projection(handle: proc::Handle) -> Result<(), String> {
+    let me = Player::read(handle, proc::Address::read(handle, 0x50f4f4)?)?;
+    let matrix = math::view_matrix(handle)?;
+    let viewport = math::viewport(handle)?;
+    if !(viewport.width > 0.0 && viewport.height > 0.0) {
+        return Err(format!("screen is {}x{}", viewport.width, viewport.height));
+    }
+
+    let ahead = me.eye() + me.view.direction() * 100.0;
+    let point = matrix
+        .project(ahead, &viewport)
+        .ok_or("the view direction projected behind the camera")?;
+    let distance = point.crosshair_distance(&viewport);
+    if !point.on_screen() || distance > viewport.width * 0.05 {
+        return Err(format!(
+            "the view direction projected {:.0} pixels from the crosshair",
+            distance
+        ));
+    }
+
+    let behind = me.eye() - me.view.direction() * 100.0;
+    if matrix.project(behind, &viewport).is_some() {
+        return Err(String::from(
+            "a point behind the camera projected onto the screen",
+        ));
+    }
+    Ok(())
+}
+
+fn world_geometry(handle: proc::Handle) -> Result<(), String> {
+    let entity_list = entities::entity_list(handle)?;
+    world::Cache::new().get(handle, &entity_list).map(|_| ())
+}
+
+fn patch_site(handle: proc::Handle, name: &str) -> Result<(), String> {
+    let spec = code::by_name(name).ok_or_else(|| format!("no patch named {}", name))?;
+    spec.is_applied(handle).map(|_| ())
+}
@@ -0,0 +1,255 @@
+//! Printing the players and items as tables, along with what happened since the last print
+//!
+//! The tables are fixed-width text by default, one of the machine-readable formats in `output`,
+//! or the full-screen `dashboard`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{self, Config, Features};
+use crate::dashboard::{self, Board, Dashboard};
+use crate::entities::{Item, Player};
+use crate::events::{self, Event};
+use crate::feature::{Feature, Setting};
+use crate::output::{self, Format, Record};
+use crate::proc::ProcessMemory;
+use crate::scheduler::WorldSnapshot;
+
+const SCHEMA: &[Setting] = &[
+    Setting {
+        section: "features",
+        key: "events",
+        description: "Print joins, deaths, damage and the like as they happen",
+    },
+    Setting {
+        section: "output",
+        key: "interval",
+        description: "Milliseconds between tables",
+    },
+    Setting {
+        section: "output",
+        key: "items",
+        description: "Include the items on the map",
+    },
+    Setting {
+        section: "output",
+        key: "format",
+        description: "How to print the tables: table, json, csv or dashboard",
+    },
+    Setting {
+        section: "radar",
+        key: "zoom",
+        description: "How far the dashboard's radar is zoomed in",
+    },
+];
+
+pub struct Table {
+    features: Features,
+    /// Whether the tracker's subscriber prints the events it's given, as it does for text tables
+    print_events: Arc<AtomicBool>,
+    items: bool,
+    interval: Duration,
+    format: Format,
+    radar: config::Radar,
+    /// Whether the CSV header has been printed, which only happens once
+    csv_header: bool,
+    /// The dashboard, while it's the format in use and we're attached to the game
+    dashboard: Option<Dashboard>,
+    board: Board,
+    tracker: events::Tracker,
+}
+
+impl Table {
+    pub fn new(config: &Config, board: Board) -> Table {
+        let print_events = Arc::new(AtomicBool::new(false));
+        let mut table = Table {
+            features: config.features,
+            tracker: tracker(&print_events),
+            print_events,
+            items: false,
+            interval: Duration::default(),
+            format: Format::Table,
+            radar: config.radar,
+            csv_header: false,
+            dashboard: None,
+            board,
+        };
+        table.configure(config);
+        table
+    }
+}
+
+/// A tracker that prints events while `print_events` is set
+fn tracker(print_events: &Arc<AtomicBool>) -> events::Tracker {
+    let print_events = Arc::clone(print_events);
+    let mut tracker = events::Tracker::new();
+    tracker.subscribe(move |event| {
+        if print_events.load(Ordering::Relaxed) {
+            println!("* {}", event);
+        }
+    });
+    tracker
+}
+
+impl Feature for Table {
+    fn name(&self) -> &'static str {
+        "table"
+    }
+
+    fn schema(&self) -> &'static [Setting] {
+        SCHEMA
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.features = config.features;
+        self.items = config.output.items;
+        self.interval = config.output.interval;
+        self.format = config.output.format;
+        self.radar = config.radar;
+        self.print_events.store(
+            self.features.events && self.format == Format::Table,
+            Ordering::Relaxed,
+        );
+        if self.format != Format::Dashboard {
+            // Hand the console back.
+            self.dashboard = None;
+        }
+    }
+
+    fn on_attach(&mut self, _memory: &mut dyn ProcessMemory) {
+        // Start over, rather than reporting everyone in the new game as having just joined.
+        self.tracker = tracker(&self.print_events);
+    }
+
+    fn on_detach(&mut self, _memory: &mut dyn ProcessMemory) {
+        // Let messages about waiting for the game show on the normal screen.
+        self.dashboard = None;
+    }
+
+    fn on_tick(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        _memory: &mut dyn ProcessMemory,
+    ) -> Result<Duration, String> {
+        match self.format {
+            Format::Table => self.print_table(snapshot),
+            Format::Json => {
+                let events = self.tracker.update(snapshot.players.clone());
+                let record = self.record(snapshot, &events);
+                if !record.is_empty() {
+                    println!("{}", record.to_json());
+                }
+            }
+            Format::Csv => {
+                let events = self.tracker.update(snapshot.players.clone());
+                let record = self.record(snapshot, &events);
+                if !record.is_empty() {
+                    if !self.csv_header {
+                        println!("{}", output::CSV_HEADER);
+                        self.csv_header = true;
+                    }
+                    print!("{}", record.to_csv());
+                }
+            }
+            Format::Dashboard => {
+                let events = self.tracker.update(snapshot.players.clone());
+                let dashboard = match &mut self.dashboard {
+                    Some(dashboard) => dashboard,
+                    none => none.insert(Dashboard::open()?),
+                };
+                dashboard.handle_keys(&self.features, &self.board);
+                if self.features.events {
+                    dashboard.record(&events);
+                }
+                dashboard.draw(snapshot, &self.features, &self.board, &self.radar);
+                // Keep up with keystrokes, however rarely the tables are printed.
+                return Ok(self.interval.min(dashboard::TICK));
+            }
+        }
+
+        Ok(self.interval)
+    }
+}
+
+impl Table {
+    fn print_table(&mut self, snapshot: &WorldSnapshot) {
+        if self.features.table {
+            print_header();
+            print_player(&snapshot.me);
+            for player in snapshot.players() {
+                print_player(player);
+            }
+        }
+
+        // Keep tracking while events are off, so that switching them on doesn't report
+        // everything that changed in the meantime.
+        self.tracker.update(snapshot.players.clone());
+
+        if self.features.table && self.items && !snapshot.items.is_empty() {
+            println!();
+            print_item_header();
+            for item in &snapshot.items {
+                print_item(item);
+            }
+        }
+
+        if self.features.table {
+            println!();
+        }
+    }
+
+    /// What a machine-readable format prints for this tick, going by which parts are switched on
+    fn record<'a>(&self, snapshot: &'a WorldSnapshot, events: &'a [Event]) -> Record<'a> {
+        let mut record = Record::now();
+        if self.features.table {
+            record.me = Some(&snapshot.me);
+            record.players = snapshot.players().collect();
+            if self.items {
+                record.items = &snapshot.items;
+            }
+        }
+        if self.features.events {
+            record.events = events;
+        }
+        record
+    }
+}
+
+pub fn print_header() {
+    println!("Name              Health  Armor  X           Y           Z");
+    println!("===================================================================");
+}
+
+pub fn print_player(player: &Player) {
+    println!(
+        "{:16}  {:<4}    {:<4}   {:<10}  {:<10}  {:<10}",
+        player.name,
+        player.health,
+        player.armor,
+        player.position.x,
+        player.position.y,
+        player.position.z,
+    );
+}
+
+pub fn print_item_header() {
+    println!("Item              State      X           Y           Z");
+    println!("===================================================================");
+}
+
+pub fn print_item(item: &Item) {
+    let state = if item.spawned {
+        "available"
+    } else {
+        "respawning"
+    };
+    println!(
+        "{:16}  {:<10} {:<10}  {:<10}  {:<10}",
+        item.kind.name(),
+        state,
+        item.position.x,
+        item.position.y,
+        item.position.z,
+    );
+}
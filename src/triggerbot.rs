@@ -0,0 +1,381 @@
+//! Firing automatically while the crosshair is on an enemy
+//!
+//! Unlike the aimbot, this never touches the view angles. Each tick it casts a ray from our eyes
+//! along the view direction against every enemy's hitbox, and holds down the attack flag while
+//! the ray hits one we can see.
+
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::entities;
+use crate::feature::{Feature, Setting};
+use crate::input::{Activation, InputSource};
+use crate::proc::{self, ProcessMemory, Read, Write};
+use crate::scheduler::WorldSnapshot;
+
+pub struct Settings {
+    pub enabled: bool,
+    pub activation: Activation,
+    pub input: Box<dyn InputSource>,
+    /// How long the crosshair has to stay on a target before we start firing
+    pub reaction: Duration,
+    /// How long to hold down fire for once we do
+    pub burst: Duration,
+    /// How often to check the crosshair while active
+    pub tick: Duration,
+}
+
+impl Settings {
+    pub fn new(config: &Config, input: Box<dyn InputSource>) -> Settings {
+        let triggerbot = &config.triggerbot;
+        Settings {
+            enabled: config.features.triggerbot,
+            activation: Activation::new(triggerbot.hotkey.key, triggerbot.hotkey.mode),
+            input,
+            reaction: triggerbot.reaction,
+            burst: triggerbot.burst,
+            tick: triggerbot.tick,
+        }
+    }
+
+    /// Take on a new configuration, keeping whether the triggerbot is toggled on
+    pub fn apply(&mut self, config: &Config) {
+        let triggerbot = &config.triggerbot;
+        self.enabled = config.features.triggerbot;
+        self.activation.key = triggerbot.hotkey.key;
+        self.activation.mode = triggerbot.hotkey.mode;
+        self.reaction = triggerbot.reaction;
+        self.burst = triggerbot.burst;
+        self.tick = triggerbot.tick;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Trigger {
+    Idle,
+    /// The crosshair went onto a target, and we're waiting out the reaction delay
+    Reacting {
+        since: Instant,
+    },
+    /// We're holding down fire
+    Firing {
+        since: Instant,
+    },
+}
+
+const SCHEMA: &[Setting] = &[
+    Setting {
+        section: "hotkeys",
+        key: "triggerbot",
+        description: "Key that switches it on, and whether to hold or toggle it",
+    },
+    Setting {
+        section: "triggerbot",
+        key: "tick",
+        description: "Milliseconds between crosshair checks",
+    },
+    Setting {
+        section: "triggerbot",
+        key: "reaction",
+        description: "Milliseconds the crosshair has to stay on a target before firing",
+    },
+    Setting {
+        section: "triggerbot",
+        key: "burst",
+        description: "Milliseconds to hold down fire for",
+    },
+];
+
+/// Holds down fire while the crosshair is on an enemy and its hotkey says so
+pub struct Triggerbot {
+    settings: Settings,
+    trigger: Trigger,
+}
+
+impl Triggerbot {
+    pub fn new(settings: Settings) -> Triggerbot {
+        Triggerbot {
+            settings,
+            trigger: Trigger::Idle,
+        }
+    }
+}
+
+impl Feature for Triggerbot {
+    fn name(&self) -> &'static str {
+        "triggerbot"
+    }
+
+    fn schema(&self) -> &'static [Setting] {
+        SCHEMA
+    }
+
+    fn configure(&mut self, config: &Config) {
+        self.settings.apply(config);
+    }
+
+    fn on_attach(&mut self, _memory: &mut dyn ProcessMemory) {
+        self.trigger = Trigger::Idle;
+    }
+
+    fn on_tick(
+        &mut self,
+        snapshot: &WorldSnapshot,
+        memory: &mut dyn ProcessMemory,
+    ) -> Result<Duration, String> {
+        let settings = &mut self.settings;
+        let active = settings.activation.poll(settings.input.as_mut()) && settings.enabled;
+        let on_target = active && crosshair_on_enemy(snapshot);
+        let now = snapshot.taken_at;
+        let my_addr = snapshot.my_addr;
+
+        self.trigger = match self.trigger {
+            Trigger::Idle if on_target => Trigger::Reacting { since: now },
+            Trigger::Reacting { .. } if !on_target => Trigger::Idle,
+            Trigger::Reacting { since } if now.duration_since(since) >= settings.reaction => {
+                attack(memory, my_addr, true)?;
+                Trigger::Firing { since: now }
+            }
+            // Let go when the burst is over, or straight away once the crosshair is off the
+            // target or we were switched off. Staying on target starts the next burst after
+            // another reaction delay.
+            Trigger::Firing { since }
+                if !on_target || now.duration_since(since) >= settings.burst =>
+            {
+                attack(memory, my_addr, false)?;
+                Trigger::Idle
+            }
+            trigger => trigger,
+        };
+
+        Ok(settings.tick)
+    }
+
+    fn on_detach(&mut self, memory: &mut dyn ProcessMemory) {
+        // Don't leave the trigger held down. The game may be gone already, so failing is fine.
+        if let Trigger::Firing { .. } = self.trigger {
+            if let Ok(my_addr) = proc::Address::read(&mut *memory, 0x50f4f4) {
+                let _ = false.write(memory, my_addr + entities::PLAYER_ATTACKING_OFFSET as u32);
+            }
+        }
+    }
+}
+
+/// Whether the ray from our eyes along our view direction hits a living enemy we can see
+fn crosshair_on_enemy(snapshot: &WorldSnapshot) -> bool {
+    let me = &snapshot.me;
+    if me.health <= 0 {
+        return false;
+    }
+
+    let eye = me.eye();
+    let direction = me.view.direction();
+
+    // Only the nearest player along the ray matters; anyone behind them is covered.
+    let nearest = snapshot
+        .players()
+        .filter(|p| p.health > 0)
+        .filter_map(|p| Some((p, p.hitbox().intersect(eye, direction)?)))
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    let distance = match nearest {
+        Some((target, distance)) if snapshot.mode.hostile(me, target) => distance,
+        _ => return false,
+    };
+
+    // Without the map, there's no telling whether a wall is in the way.
+    let world = match &snapshot.world {
+        Some(world) => world,
+        None => return false,
+    };
+    let hit = eye + direction * distance;
+    world.visible(eye, hit)
+}
+
+fn attack(
+    memory: &mut dyn ProcessMemory,
+    player_addr: proc::Address,
+    attacking: bool,
+) -> Result<(), String> {
+    attacking
+        .write(
+            memory,
+            player_addr + entities::PLAYER_ATTACKING_OFFSET as u32,
+        )
+        .map_err(|err| format!("failed to set attack flag: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Player;
+    use crate::game::GameMode;
+    use crate::input::{Key, Mode};
+    use crate::math::{Angles, Vec3};
+    use crate::proc::fake::FakeMemory;
+    use crate::world::{Cell, CellKind, World};
+    use std::rc::Rc;
+
+    const MY_ADDR: proc::Address = 0x1000;
+
+    struct Held;
+
+    impl InputSource for Held {
+        fn is_down(&mut self, _key: Key) -> bool {
+            true
+        }
+    }
+
+    fn player(x: f32, y: f32, team: i32) -> Player {
+        Player {
+            slot: Some(1),
+            position: Vec3::new(x, y, 4.5),
+            feet: Vec3::new(x, y, 0.0),
+            eye_height: 4.5,
+            above_eye: 0.7,
+            health: 100,
+            team,
+            ..Player::default()
+        }
+    }
+
+    /// A 4x4 map, open from height 0 to 16 except for the given walls
+    fn world(walls: &[(usize, usize)]) -> Rc<World> {
+        let open = Cell {
+            kind: CellKind::Space,
+            floor: 0,
+            ceil: 16,
+            vdelta: 0,
+        };
+        let mut cells = vec![open; 16];
+        for &(x, y) in walls {
+            cells[y * 4 + x] = Cell::SOLID;
+        }
+        Rc::new(World::new(2, cells).unwrap())
+    }
+
+    /// Us near one edge of the map looking along -y (a yaw of 0), in team deathmatch
+    fn snapshot(taken_at: Instant, players: Vec<Player>) -> WorldSnapshot {
+        WorldSnapshot {
+            taken_at,
+            my_addr: MY_ADDR,
+            me: Player {
+                view: Angles::new(0.0, 0.0),
+                ..player(2.5, 3.5, 0)
+            },
+            players: players.into_iter().map(Some).collect(),
+            mode: GameMode(0),
+            items: Vec::new(),
+            world: Some(world(&[])),
+        }
+    }
+
+    #[test]
+    fn crosshair_on_enemy_needs_a_living_visible_enemy_in_front() {
+        let enemy = player(2.5, 0.8, 1);
+
+        let mut dead = snapshot(Instant::now(), vec![enemy.clone()]);
+        dead.me.health = 0;
+        assert!(!crosshair_on_enemy(&dead));
+
+        let cases = [
+            ("ahead", vec![enemy.clone()], true),
+            ("nobody", vec![], false),
+            ("off to the side", vec![player(0.5, 0.8, 1)], false),
+            (
+                "dead",
+                vec![Player {
+                    health: 0,
+                    ..enemy.clone()
+                }],
+                false,
+            ),
+            ("teammate", vec![player(2.5, 0.8, 0)], false),
+            ("spectator", vec![player(2.5, 0.8, 4)], false),
+            // The teammate in between takes the shot.
+            ("covered", vec![enemy.clone(), player(2.5, 2.0, 0)], false),
+        ];
+        for (name, players, expected) in cases.iter().cloned() {
+            let snapshot = snapshot(Instant::now(), players);
+            assert_eq!(crosshair_on_enemy(&snapshot), expected, "{}", name);
+        }
+
+        // Free for all makes everyone an enemy.
+        let mut ffa = snapshot(Instant::now(), vec![player(2.5, 0.8, 0)]);
+        ffa.mode = GameMode(2);
+        assert!(crosshair_on_enemy(&ffa));
+
+        let mut walled = snapshot(Instant::now(), vec![enemy.clone()]);
+        walled.world = Some(world(&[(2, 2)]));
+        assert!(!crosshair_on_enemy(&walled));
+
+        let mut unknown = snapshot(Instant::now(), vec![enemy]);
+        unknown.world = None;
+        assert!(!crosshair_on_enemy(&unknown));
+    }
+
+    #[test]
+    fn reacts_fires_a_burst_and_lets_go() {
+        let mut triggerbot = Triggerbot::new(Settings {
+            enabled: true,
+            activation: Activation::new(Key::Char('E'), Mode::Hold),
+            input: Box::new(Held),
+            reaction: Duration::from_millis(80),
+            burst: Duration::from_millis(150),
+            tick: Duration::from_millis(5),
+        });
+        let mut memory = FakeMemory::new();
+        let attack_flag = MY_ADDR + entities::PLAYER_ATTACKING_OFFSET as u32;
+        memory.put(attack_flag, &[0]);
+
+        let start = Instant::now();
+        let enemy = vec![player(2.5, 0.8, 1)];
+        // (milliseconds in, whether the crosshair is on the enemy, whether fire is held down)
+        let sequence = [
+            (0, false, false),
+            // The reaction delay
+            (10, true, false),
+            (50, true, false),
+            (90, true, true),
+            // A full burst
+            (200, true, true),
+            (240, true, false),
+            // Leaving while still reacting
+            (245, true, false),
+            (260, false, false),
+            // Leaving mid-burst lets go straight away.
+            (300, true, false),
+            (380, true, true),
+            (390, false, false),
+        ];
+        for &(ms, on_target, firing) in &sequence {
+            let players = if on_target { enemy.clone() } else { vec![] };
+            let snapshot = snapshot(start + Duration::from_millis(ms), players);
+            triggerbot.on_tick(&snapshot, &mut memory).unwrap();
+            assert_eq!(
+                bool::read(&mut memory, attack_flag),
+                Ok(firing),
+                "{} ms",
+                ms
+            );
+        }
+
+        // Detaching mid-burst lets go too.
+        triggerbot
+            .on_tick(
+                &snapshot(start + Duration::from_millis(400), enemy.clone()),
+                &mut memory,
+            )
+            .unwrap();
+        triggerbot
+            .on_tick(
+                &snapshot(start + Duration::from_millis(480), enemy),
+                &mut memory,
+            )
+            .unwrap();
+        assert_eq!(bool::read(&mut memory, attack_flag), Ok(true));
+        memory.put_i32(0x50f4f4, MY_ADDR as i32);
+        triggerbot.on_detach(&mut memory);
+        assert_eq!(bool::read(&mut memory, attack_flag), Ok(false));
+    }
+}
@@ -0,0 +1,176 @@
+//! The player's weapons and how they behave
+
+use crate::entities;
+use crate::proc::{self, ProcessMemory, Read};
+
+/// Offset of the gun type within a weapon object
+pub const WEAPON_TYPE_OFFSET: isize = 0x4;
+/// Offset of the pointer to the weapon's `guninfo`, the stats shared by every gun of its type
+pub const WEAPON_INFO_OFFSET: isize = 0xC;
+/// Offset of the number of shots fired in the current burst within a weapon object
+pub const WEAPON_SHOTS_OFFSET: isize = 0x1C;
+
+// A `guninfo` starts with a 260 byte model name followed by 16 bit stats; these are the three
+// the game works each shot's kick out from.
+pub const GUNINFO_RECOIL_INCREASE_OFFSET: isize = 0x11E;
+pub const GUNINFO_RECOIL_BASE_OFFSET: isize = 0x120;
+pub const GUNINFO_MAX_RECOIL_OFFSET: isize = 0x122;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weapon {
+    Knife,
+    Pistol,
+    Carbine,
+    Shotgun,
+    Subgun,
+    Sniper,
+    Assault,
+    CombatPistol,
+    Grenade,
+    Akimbo,
+}
+
+impl Weapon {
+    pub fn from_id(id: i32) -> Option<Weapon> {
+        match id {
+            0 => Some(Weapon::Knife),
+            1 => Some(Weapon::Pistol),
+            2 => Some(Weapon::Carbine),
+            3 => Some(Weapon::Shotgun),
+            4 => Some(Weapon::Subgun),
+            5 => Some(Weapon::Sniper),
+            6 => Some(Weapon::Assault),
+            7 => Some(Weapon::CombatPistol),
+            8 => Some(Weapon::Grenade),
+            9 => Some(Weapon::Akimbo),
+            _ => None,
+        }
+    }
+
+    /// Read the weapon currently held by the player at `player_addr`
+    pub fn read_current<M: ProcessMemory>(
+        mut memory: M,
+        player_addr: proc::Address,
+    ) -> Result<Weapon, String> {
+        let weapon_addr = proc::Address::read(
+            &mut memory,
+            player_addr + entities::PLAYER_WEAPON_OFFSET as u32,
+        )?;
+        let id = i32::read(memory, weapon_addr + WEAPON_TYPE_OFFSET as u32)?;
+        Weapon::from_id(id).ok_or_else(|| format!("unknown weapon id: {}", id))
+    }
+
+    /// Read how many shots the player at `player_addr` has fired in their current burst
+    pub fn read_shots<M: ProcessMemory>(
+        mut memory: M,
+        player_addr: proc::Address,
+    ) -> Result<i32, String> {
+        let weapon_addr = proc::Address::read(
+            &mut memory,
+            player_addr + entities::PLAYER_WEAPON_OFFSET as u32,
+        )?;
+        i32::read(memory, weapon_addr + WEAPON_SHOTS_OFFSET as u32)
+    }
+}
+
+/// How a gun kicks the view up, as the game's `guninfo` has it
+///
+/// Only the kick is read. Spread is deliberately left alone: the game rolls it afresh for every
+/// shot, so there's no way to know ahead of a shot which way to lean against it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Recoil {
+    /// Kick of every shot, in tenths of a degree
+    pub base: i16,
+    /// How fast the kick grows over a burst: by (shots so far / increase)² degrees
+    pub increase: i16,
+    /// Most a single shot kicks, in tenths of a degree
+    pub max: i16,
+}
+
+impl Recoil {
+    /// Read the recoil of the weapon currently held by the player at `player_addr`
+    pub fn read<M: ProcessMemory>(
+        mut memory: M,
+        player_addr: proc::Address,
+    ) -> Result<Recoil, String> {
+        let weapon_addr = proc::Address::read(
+            &mut memory,
+            player_addr + entities::PLAYER_WEAPON_OFFSET as u32,
+        )?;
+        let info_addr = proc::Address::read(&mut memory, weapon_addr + WEAPON_INFO_OFFSET as u32)?;
+        let stat = |memory: &mut M, offset: isize| -> Result<i16, String> {
+            let bytes = memory.read_bytes(info_addr + offset as u32, 2)?;
+            Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        Ok(Recoil {
+            base: stat(&mut memory, GUNINFO_RECOIL_BASE_OFFSET)?,
+            increase: stat(&mut memory, GUNINFO_RECOIL_INCREASE_OFFSET)?,
+            max: stat(&mut memory, GUNINFO_MAX_RECOIL_OFFSET)?,
+        })
+    }
+
+    /// Degrees a shot kicks the view up with `shot` shots of the burst already fired, worked out
+    /// the way the game does
+    pub fn kick(self, shot: i32) -> f32 {
+        let growth = if self.increase > 0 {
+            (shot as f32 / f32::from(self.increase)).powi(2)
+        } else {
+            0.0
+        };
+        (growth + f32::from(self.base) / 10.0).min(f32::from(self.max) / 10.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proc::fake::FakeMemory;
+
+    #[test]
+    fn recoil_is_read_from_the_guninfo() {
+        let (player, weapon, info) = (0x1000, 0x2000, 0x3000);
+        let mut memory = FakeMemory::new();
+        memory.put_i32(
+            player + entities::PLAYER_WEAPON_OFFSET as u32,
+            weapon as i32,
+        );
+        memory.put_i32(weapon + WEAPON_INFO_OFFSET as u32, info as i32);
+        // recoilincrease, recoilbase and maxrecoil sit next to each other.
+        memory.put(
+            info + GUNINFO_RECOIL_INCREASE_OFFSET as u32,
+            &[2, 0, 20, 0, 30, 0],
+        );
+
+        let recoil = Recoil::read(&mut memory, player).unwrap();
+        assert_eq!(
+            recoil,
+            Recoil {
+                base: 20,
+                increase: 2,
+                max: 30
+            }
+        );
+
+        // A weapon without its guninfo fails rather than making a kick up.
+        memory.put_i32(weapon + WEAPON_INFO_OFFSET as u32, 0x4000);
+        assert!(Recoil::read(&mut memory, player).is_err());
+    }
+
+    #[test]
+    fn kick_grows_over_a_burst_up_to_the_max() {
+        let recoil = Recoil {
+            base: 20,
+            increase: 2,
+            max: 30,
+        };
+        let kicks: Vec<f32> = (0..4).map(|shot| recoil.kick(shot)).collect();
+        assert_eq!(kicks, [2.0, 2.25, 3.0, 3.0]);
+
+        // Guns with no increase always kick the same.
+        let flat = Recoil {
+            increase: 0,
+            ..recoil
+        };
+        assert_eq!(flat.kick(10), 2.0);
+    }
+}
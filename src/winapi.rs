@@ -5,8 +5,10 @@
 
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
+#![allow(clippy::upper_case_acronyms)]
 
 pub type c_char = i8;
+pub type c_uchar = u8;
 pub type c_short = i16;
 pub type c_ushort = u16;
 pub type c_int = i32;
@@ -15,8 +17,11 @@ pub type c_ulong = u32;
 pub enum c_void {}
 
 pub type BOOL = c_int;
+pub type BYTE = c_uchar;
 pub type CHAR = c_char;
 pub type DWORD = c_ulong;
+pub type WORD = c_ushort;
+pub type WCHAR = u16;
 pub type LONG = c_long;
 pub type ULONG_PTR = usize;
 pub type PDWORD = *mut DWORD;
@@ -28,12 +33,20 @@ pub type USHORT = c_ushort;
 pub type SIZE_T = ULONG_PTR;
 pub type HANDLE = *mut c_void;
 pub type HLOCAL = HANDLE;
+pub type HMODULE = HANDLE;
 pub type LPPROCESSENTRY32 = *mut PROCESSENTRY32;
+pub type LPMODULEENTRY32 = *mut MODULEENTRY32;
 pub type LANGID = USHORT;
 pub type LPSTR = *mut CHAR;
 pub type va_list = *mut c_char;
+pub type PHANDLER_ROUTINE = Option<unsafe extern "system" fn(CtrlType: DWORD) -> BOOL>;
 
 pub const TH32CS_SNAPPROCESS: DWORD = 0x00000002;
+pub const TH32CS_SNAPMODULE: DWORD = 0x00000008;
+pub const TH32CS_SNAPMODULE32: DWORD = 0x00000010;
+
+pub const MAX_PATH: usize = 260;
+pub const MAX_MODULE_NAME32: usize = 255;
 
 pub const ERROR_NO_MORE_FILES: DWORD = 18;
 
@@ -43,7 +56,32 @@ pub const SYNCHRONIZE: DWORD = 0x00100000;
 
 pub const STILL_ACTIVE: DWORD = 259;
 
+pub const STD_INPUT_HANDLE: DWORD = -10i32 as DWORD;
+pub const STD_OUTPUT_HANDLE: DWORD = -11i32 as DWORD;
+pub const INVALID_HANDLE_VALUE: HANDLE = -1isize as HANDLE;
+
+pub const ENABLE_PROCESSED_OUTPUT: DWORD = 0x0001;
+pub const ENABLE_VIRTUAL_TERMINAL_PROCESSING: DWORD = 0x0004;
+pub const ENABLE_LINE_INPUT: DWORD = 0x0002;
+pub const ENABLE_ECHO_INPUT: DWORD = 0x0004;
+
+pub const KEY_EVENT: WORD = 0x0001;
+
+pub const CTRL_C_EVENT: DWORD = 0;
+pub const CTRL_BREAK_EVENT: DWORD = 1;
+
+pub const VK_LBUTTON: c_int = 0x01;
+pub const VK_RBUTTON: c_int = 0x02;
+pub const VK_MBUTTON: c_int = 0x04;
+pub const VK_XBUTTON1: c_int = 0x05;
+pub const VK_XBUTTON2: c_int = 0x06;
+pub const VK_TAB: c_int = 0x09;
+pub const VK_SHIFT: c_int = 0x10;
+pub const VK_CONTROL: c_int = 0x11;
+pub const VK_MENU: c_int = 0x12;
 pub const VK_CAPITAL: c_int = 0x14;
+pub const VK_SPACE: c_int = 0x20;
+pub const VK_F1: c_int = 0x70;
 
 pub const MEM_COMMIT: DWORD = 0x1000;
 pub const MEM_RESERVE: DWORD = 0x2000;
@@ -68,13 +106,50 @@ pub struct PROCESSENTRY32 {
     pub th32ParentProcessID: DWORD,
     pub pcPriClassBase: LONG,
     pub dwFlags: DWORD,
-    pub szExeFile: [CHAR; 260],
+    pub szExeFile: [CHAR; MAX_PATH],
+}
+
+#[repr(C)]
+pub struct MODULEENTRY32 {
+    pub dwSize: DWORD,
+    pub th32ModuleID: DWORD,
+    pub th32ProcessID: DWORD,
+    pub GlblcntUsage: DWORD,
+    pub ProccntUsage: DWORD,
+    pub modBaseAddr: *mut BYTE,
+    pub modBaseSize: DWORD,
+    pub hModule: HMODULE,
+    pub szModule: [CHAR; MAX_MODULE_NAME32 + 1],
+    pub szExePath: [CHAR; MAX_PATH],
+}
+
+#[repr(C)]
+pub struct KEY_EVENT_RECORD {
+    pub bKeyDown: BOOL,
+    pub wRepeatCount: WORD,
+    pub wVirtualKeyCode: WORD,
+    pub wVirtualScanCode: WORD,
+    /// A union of the character as UTF-16 or as ASCII, which `ReadConsoleInputA` fills in
+    pub uChar: WCHAR,
+    pub dwControlKeyState: DWORD,
+}
+
+/// A console input event
+///
+/// `Event` is really a union of every kind of event, of which the key event is the largest, so
+/// this has the right size and layout for all of them. Check `EventType` before reading it.
+#[repr(C)]
+pub struct INPUT_RECORD {
+    pub EventType: WORD,
+    pub Event: KEY_EVENT_RECORD,
 }
 
 extern "system" {
     pub fn CreateToolhelp32Snapshot(dwFlags: DWORD, th32ProcessID: DWORD) -> HANDLE;
     pub fn Process32First(hSnapshot: HANDLE, lppe: LPPROCESSENTRY32) -> BOOL;
     pub fn Process32Next(hSnapshot: HANDLE, lppe: LPPROCESSENTRY32) -> BOOL;
+    pub fn Module32First(hSnapshot: HANDLE, lpme: LPMODULEENTRY32) -> BOOL;
+    pub fn Module32Next(hSnapshot: HANDLE, lpme: LPMODULEENTRY32) -> BOOL;
     pub fn GetLastError() -> DWORD;
     pub fn GetExitCodeProcess(hProcess: HANDLE, lpExitCode: LPDWORD) -> BOOL;
     pub fn OpenProcess(dwDesiredAccess: DWORD, bInheritHandle: BOOL, dwProcessId: DWORD) -> HANDLE;
@@ -94,6 +169,7 @@ extern "system" {
         lpNumberOfBytesWritten: *mut SIZE_T,
     ) -> BOOL;
     pub fn GetKeyState(nVirtKey: c_int) -> SHORT;
+    pub fn GetAsyncKeyState(vKey: c_int) -> SHORT;
     pub fn VirtualAllocEx(
         hProcess: HANDLE,
         lpAddress: LPVOID,
@@ -118,6 +194,18 @@ extern "system" {
         Arguments: *mut va_list,
     ) -> DWORD;
     pub fn LocalFree(hMem: HLOCAL) -> HLOCAL;
+    pub fn GetStdHandle(nStdHandle: DWORD) -> HANDLE;
+    pub fn GetConsoleMode(hConsoleHandle: HANDLE, lpMode: LPDWORD) -> BOOL;
+    pub fn SetConsoleMode(hConsoleHandle: HANDLE, dwMode: DWORD) -> BOOL;
+    pub fn SetConsoleCtrlHandler(HandlerRoutine: PHANDLER_ROUTINE, Add: BOOL) -> BOOL;
+    pub fn GetNumberOfConsoleInputEvents(hConsoleInput: HANDLE, lpcNumberOfEvents: LPDWORD)
+        -> BOOL;
+    pub fn ReadConsoleInputA(
+        hConsoleInput: HANDLE,
+        lpBuffer: *mut INPUT_RECORD,
+        nLength: DWORD,
+        lpNumberOfEventsRead: LPDWORD,
+    ) -> BOOL;
 }
 
 /// Creates a language identifier from a primary language identifier and a sublanguage identifier.
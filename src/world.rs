@@ -0,0 +1,359 @@
+//! The map's square-grid geometry and line-of-sight tests against it
+//!
+//! An AssaultCube map is a square grid of cells ("cubes"), each one either solid or open between
+//! a floor and a ceiling height. That makes visibility a 2D grid traversal: walk every cell the
+//! line of sight passes over, and check that it stays between the floor and ceiling of each one.
+
+use std::rc::Rc;
+
+use crate::entities;
+use crate::math::Vec3;
+use crate::proc::{self, ProcessMemory, Read};
+
+pub const WORLD_ADDR: proc::Address = 0x50a1f8;
+pub const SFACTOR_ADDR: proc::Address = 0x50a200;
+
+/// The size of a `sqr` in the client's world array
+pub const CELL_SIZE: usize = 16;
+const CELL_TYPE_OFFSET: usize = 0;
+const CELL_FLOOR_OFFSET: usize = 1;
+const CELL_CEIL_OFFSET: usize = 2;
+const CELL_VDELTA_OFFSET: usize = 9;
+
+// Maps are at most 2^10 cells on a side; anything bigger means we read garbage.
+const MAX_SFACTOR: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    Solid,
+    /// A diagonal wall, filling half the cell
+    Corner,
+    /// Open cell whose floor is a heightfield
+    FloorHeightfield,
+    /// Open cell whose ceiling is a heightfield
+    CeilHeightfield,
+    Space,
+    SemiSolid,
+}
+
+impl CellKind {
+    pub fn from_raw(kind: u8) -> CellKind {
+        match kind {
+            1 => CellKind::Corner,
+            2 => CellKind::FloorHeightfield,
+            3 => CellKind::CeilHeightfield,
+            4 => CellKind::Space,
+            5 => CellKind::SemiSolid,
+            // Treat anything unknown as a wall; it's the conservative choice for visibility.
+            _ => CellKind::Solid,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub kind: CellKind,
+    pub floor: i8,
+    pub ceil: i8,
+    /// Heightfield offset of this cell's corner, in quarter units
+    pub vdelta: u8,
+}
+
+impl Cell {
+    pub const SOLID: Cell = Cell {
+        kind: CellKind::Solid,
+        floor: 0,
+        ceil: 0,
+        vdelta: 0,
+    };
+
+    /// Whether nothing can be seen through any part of this cell
+    ///
+    /// Corners are only half filled, but working out which half a ray passes through isn't worth
+    /// it: treating them as walls errs on the side of not shooting at things we can't see.
+    pub fn blocks(&self) -> bool {
+        match self.kind {
+            CellKind::Solid | CellKind::Corner | CellKind::SemiSolid => true,
+            CellKind::Space | CellKind::FloorHeightfield | CellKind::CeilHeightfield => false,
+        }
+    }
+
+    /// The lowest point of the open space in this cell
+    pub fn floor_height(&self) -> f32 {
+        match self.kind {
+            // Heightfields dip below the nominal floor (and rise above the nominal ceiling).
+            CellKind::FloorHeightfield => self.floor as f32 - self.vdelta as f32 / 4.0,
+            _ => self.floor as f32,
+        }
+    }
+
+    /// The highest point of the open space in this cell
+    pub fn ceil_height(&self) -> f32 {
+        match self.kind {
+            CellKind::CeilHeightfield => self.ceil as f32 + self.vdelta as f32 / 4.0,
+            _ => self.ceil as f32,
+        }
+    }
+}
+
+pub struct World {
+    /// The map is `1 << sfactor` cells on a side
+    pub sfactor: u32,
+    /// Cells in row-major order, i.e. indexed by `y * size + x`
+    pub cells: Vec<Cell>,
+}
+
+impl World {
+    pub fn new(sfactor: u32, cells: Vec<Cell>) -> Result<World, String> {
+        if sfactor > MAX_SFACTOR {
+            return Err(format!("implausible map size factor: {}", sfactor));
+        }
+        let size = 1usize << sfactor;
+        if cells.len() != size * size {
+            return Err(format!(
+                "expected {} cells for a map of size {}, got {}",
+                size * size,
+                size,
+                cells.len()
+            ));
+        }
+        Ok(World { sfactor, cells })
+    }
+
+    /// Read the geometry the client has loaded at the given address
+    pub fn read_at<M: ProcessMemory>(
+        mut memory: M,
+        addr: proc::Address,
+        sfactor: u32,
+    ) -> Result<World, String> {
+        if sfactor > MAX_SFACTOR {
+            return Err(format!("implausible map size factor: {}", sfactor));
+        }
+
+        let size = 1usize << sfactor;
+        let raw = memory.read_bytes(addr, size * size * CELL_SIZE)?;
+        if raw.len() != size * size * CELL_SIZE {
+            return Err(String::from("short read of world geometry"));
+        }
+
+        let cells = raw
+            .chunks_exact(CELL_SIZE)
+            .map(|c| Cell {
+                kind: CellKind::from_raw(c[CELL_TYPE_OFFSET]),
+                floor: c[CELL_FLOOR_OFFSET] as i8,
+                ceil: c[CELL_CEIL_OFFSET] as i8,
+                vdelta: c[CELL_VDELTA_OFFSET],
+            })
+            .collect();
+
+        World::new(sfactor, cells)
+    }
+
+    pub fn size(&self) -> usize {
+        1 << self.sfactor
+    }
+
+    /// The cell at the given grid coordinates; everything outside the map is solid
+    pub fn cell(&self, x: i32, y: i32) -> &Cell {
+        let size = self.size() as i32;
+        if x < 0 || y < 0 || x >= size || y >= size {
+            return &Cell::SOLID;
+        }
+        &self.cells[(y * size + x) as usize]
+    }
+
+    /// Whether the straight line between two points passes through open space only
+    pub fn visible(&self, from: Vec3, to: Vec3) -> bool {
+        let Vec3 {
+            x: dx,
+            y: dy,
+            z: dz,
+        } = to - from;
+
+        let mut cell_x = from.x.floor() as i32;
+        let mut cell_y = from.y.floor() as i32;
+        let end_x = to.x.floor() as i32;
+        let end_y = to.y.floor() as i32;
+
+        // Walk the grid cell by cell (Amanatides & Woo), tracking the fraction `t` of the line at
+        // which we enter and leave each cell.
+        let step_x = if dx > 0.0 { 1 } else { -1 };
+        let step_y = if dy > 0.0 { 1 } else { -1 };
+        let delta_x = if dx != 0.0 {
+            (1.0 / dx).abs()
+        } else {
+            f32::INFINITY
+        };
+        let delta_y = if dy != 0.0 {
+            (1.0 / dy).abs()
+        } else {
+            f32::INFINITY
+        };
+        // A line that doesn't move along an axis never crosses into the next cell along it. That
+        // has to be spelled out: starting on a cell boundary would otherwise make it 0 * inf = NaN.
+        let mut next_x = if dx > 0.0 {
+            (cell_x as f32 + 1.0 - from.x) * delta_x
+        } else if dx < 0.0 {
+            (from.x - cell_x as f32) * delta_x
+        } else {
+            f32::INFINITY
+        };
+        let mut next_y = if dy > 0.0 {
+            (cell_y as f32 + 1.0 - from.y) * delta_y
+        } else if dy < 0.0 {
+            (from.y - cell_y as f32) * delta_y
+        } else {
+            f32::INFINITY
+        };
+
+        let mut t_enter = 0.0f32;
+        // A line can't cross more cells than this, which guards against float edge cases.
+        let max_steps = (end_x - cell_x).abs() + (end_y - cell_y).abs() + 1;
+
+        for _ in 0..=max_steps {
+            let t_exit = next_x.min(next_y).min(1.0);
+
+            let cell = self.cell(cell_x, cell_y);
+            if cell.blocks() {
+                return false;
+            }
+
+            // Height varies linearly along the line, so checking both ends of the segment within
+            // this cell covers everything in between.
+            let (z_enter, z_exit) = (from.z + dz * t_enter, from.z + dz * t_exit);
+            if z_enter.min(z_exit) < cell.floor_height() || z_enter.max(z_exit) > cell.ceil_height()
+            {
+                return false;
+            }
+
+            if t_exit >= 1.0 || (cell_x == end_x && cell_y == end_y) {
+                return true;
+            }
+
+            t_enter = t_exit;
+            if next_x < next_y {
+                cell_x += step_x;
+                next_x += delta_x;
+            } else {
+                cell_y += step_y;
+                next_y += delta_y;
+            }
+        }
+
+        true
+    }
+}
+
+/// Keeps the client's world geometry around between ticks, re-reading it only when the map
+/// changes
+///
+/// The client allocates a new world whenever a map is loaded, but a map of the same size may well
+/// get the same memory as the last one. The map's entities tell them apart.
+pub struct Cache {
+    /// The address, size factor and `entities::map_fingerprint` of the cached world
+    key: Option<(proc::Address, u32, u64)>,
+    world: Option<Rc<World>>,
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache {
+            key: None,
+            world: None,
+        }
+    }
+
+    /// The current world, shared so that it can be handed around without copying it
+    ///
+    /// `entity_list` is the map's entities, as read by `entities::entity_list`.
+    pub fn get<M: ProcessMemory>(
+        &mut self,
+        mut memory: M,
+        entity_list: &[u8],
+    ) -> Result<Rc<World>, String> {
+        let addr = proc::Address::read(&mut memory, WORLD_ADDR)?;
+        let sfactor = u32::read(&mut memory, SFACTOR_ADDR)?;
+        let key = Some((addr, sfactor, entities::map_fingerprint(entity_list)));
+
+        if self.key != key || self.world.is_none() {
+            self.world = Some(Rc::new(World::read_at(memory, addr, sfactor)?));
+            self.key = key;
+        }
+
+        Ok(Rc::clone(self.world.as_ref().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPEN: Cell = Cell {
+        kind: CellKind::Space,
+        floor: 0,
+        ceil: 16,
+        vdelta: 0,
+    };
+
+    /// A 4x4 map, open from height 0 to 16 except for the given walls
+    fn world(walls: &[(usize, usize)]) -> World {
+        let mut cells = vec![OPEN; 16];
+        for &(x, y) in walls {
+            cells[y * 4 + x] = Cell::SOLID;
+        }
+        World::new(2, cells).unwrap()
+    }
+
+    #[test]
+    fn open_cells_are_visible() {
+        let world = world(&[]);
+        assert!(world.visible(Vec3::new(0.5, 0.5, 4.0), Vec3::new(3.5, 3.5, 4.0)));
+        assert!(world.visible(Vec3::new(3.5, 0.2, 4.0), Vec3::new(0.2, 2.9, 8.0)));
+        // Within a single cell
+        assert!(world.visible(Vec3::new(1.2, 1.2, 4.0), Vec3::new(1.8, 1.7, 4.0)));
+    }
+
+    #[test]
+    fn walls_block() {
+        let world = world(&[(2, 1)]);
+        assert!(!world.visible(Vec3::new(0.5, 1.5, 4.0), Vec3::new(3.5, 1.5, 4.0)));
+        assert!(!world.visible(Vec3::new(3.5, 1.5, 4.0), Vec3::new(0.5, 1.5, 4.0)));
+        // Passing by on the row above
+        assert!(world.visible(Vec3::new(0.5, 2.5, 4.0), Vec3::new(3.5, 2.5, 4.0)));
+    }
+
+    #[test]
+    fn axis_parallel_rays_from_cell_boundaries() {
+        let world = world(&[(1, 2)]);
+        // dx == 0, starting exactly on the boundary between columns 0 and 1
+        assert!(world.visible(Vec3::new(1.0, 0.5, 4.0), Vec3::new(1.0, 1.5, 4.0)));
+        assert!(!world.visible(Vec3::new(1.0, 0.5, 4.0), Vec3::new(1.0, 3.5, 4.0)));
+        assert!(world.visible(Vec3::new(2.0, 0.5, 4.0), Vec3::new(2.0, 3.5, 4.0)));
+        // dy == 0 on a row boundary, backwards
+        assert!(!world.visible(Vec3::new(3.5, 2.0, 4.0), Vec3::new(0.5, 2.0, 4.0)));
+        assert!(world.visible(Vec3::new(3.5, 1.0, 4.0), Vec3::new(0.5, 1.0, 4.0)));
+    }
+
+    #[test]
+    fn floors_and_ceilings_block() {
+        let mut world = world(&[]);
+        world.cells[4 + 2] = Cell { floor: 6, ..OPEN };
+        assert!(!world.visible(Vec3::new(0.5, 1.5, 4.0), Vec3::new(3.5, 1.5, 4.0)));
+        assert!(world.visible(Vec3::new(0.5, 1.5, 8.0), Vec3::new(3.5, 1.5, 8.0)));
+        // Climbing out through the ceiling
+        assert!(!world.visible(Vec3::new(0.5, 0.5, 4.0), Vec3::new(3.5, 0.5, 20.0)));
+    }
+
+    #[test]
+    fn outside_the_map_is_solid() {
+        let world = world(&[]);
+        assert!(!world.visible(Vec3::new(0.5, 0.5, 4.0), Vec3::new(5.5, 0.5, 4.0)));
+        assert!(!world.visible(Vec3::new(-0.5, 0.5, 4.0), Vec3::new(1.5, 0.5, 4.0)));
+    }
+
+    #[test]
+    fn new_checks_the_cell_count() {
+        assert!(World::new(2, vec![OPEN; 15]).is_err());
+        assert!(World::new(MAX_SFACTOR + 1, Vec::new()).is_err());
+    }
+}